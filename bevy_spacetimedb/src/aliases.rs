@@ -1,8 +1,11 @@
 use bevy::prelude::MessageReader;
 
 use crate::{
-    DeleteMessage, InsertMessage, InsertUpdateMessage, ReducerResultMessage, StdbConnectedMessage,
-    StdbConnectionErrorMessage, StdbDisconnectedMessage, UpdateMessage,
+    DeleteMessage, InsertMessage, InsertUpdateMessage, ProcedureResultMessage,
+    ReducerCallFailedMessage, ReducerCallResultMessage, ReducerCallTimeoutMessage,
+    ReducerResultMessage, StdbConnectedMessage, StdbConnectionErrorMessage, StdbDisconnectedMessage,
+    StdbTransactionMessage, SubscriptionAppliedMessage, SubscriptionEndedMessage,
+    SubscriptionErrorMessage, UpdateMessage,
 };
 
 /// A type alias for a Bevy message reader for InsertMessage<T>.
@@ -20,11 +23,39 @@ pub type ReadInsertUpdateMessage<'w, 's, T> = MessageReader<'w, 's, InsertUpdate
 /// A type alias for a Bevy message reader for ReducerResultMessage<T>.
 pub type ReadReducerMessage<'w, 's, T> = MessageReader<'w, 's, ReducerResultMessage<T>>;
 
-/// A type alias for a Bevy message reader for StdbConnectedMessage.
-pub type ReadStdbConnectedMessage<'w, 's> = MessageReader<'w, 's, StdbConnectedMessage>;
+/// A type alias for a Bevy message reader for ProcedureResultMessage<T>.
+pub type ReadProcedureMessage<'w, 's, T> = MessageReader<'w, 's, ProcedureResultMessage<T>>;
 
-/// A type alias for a Bevy message reader for StdbDisconnectedMessage.
-pub type ReadStdbDisconnectedMessage<'w, 's> = MessageReader<'w, 's, StdbDisconnectedMessage>;
+/// A type alias for a Bevy message reader for ReducerCallFailedMessage<R>.
+pub type ReadReducerCallFailedMessage<'w, 's, R> = MessageReader<'w, 's, ReducerCallFailedMessage<R>>;
 
-/// A type alias for a Bevy message reader for StdbConnectionErrorMessage.
-pub type ReadStdbConnectionErrorMessage<'w, 's> = MessageReader<'w, 's, StdbConnectionErrorMessage>;
+/// A type alias for a Bevy message reader for ReducerCallResultMessage<R>.
+pub type ReadReducerCallResultMessage<'w, 's, R> = MessageReader<'w, 's, ReducerCallResultMessage<R>>;
+
+/// A type alias for a Bevy message reader for ReducerCallTimeoutMessage<R>.
+pub type ReadReducerCallTimeoutMessage<'w, 's, R> = MessageReader<'w, 's, ReducerCallTimeoutMessage<R>>;
+
+/// A type alias for a Bevy message reader for StdbConnectedMessage<C>.
+pub type ReadStdbConnectedMessage<'w, 's, C> = MessageReader<'w, 's, StdbConnectedMessage<C>>;
+
+/// A type alias for a Bevy message reader for StdbDisconnectedMessage<C>.
+pub type ReadStdbDisconnectedMessage<'w, 's, C> = MessageReader<'w, 's, StdbDisconnectedMessage<C>>;
+
+/// A type alias for a Bevy message reader for StdbConnectionErrorMessage<C>.
+pub type ReadStdbConnectionErrorMessage<'w, 's, C> =
+    MessageReader<'w, 's, StdbConnectionErrorMessage<C>>;
+
+/// A type alias for a Bevy message reader for SubscriptionAppliedMessage<S>.
+pub type ReadSubscriptionAppliedMessage<'w, 's, S> =
+    MessageReader<'w, 's, SubscriptionAppliedMessage<S>>;
+
+/// A type alias for a Bevy message reader for SubscriptionErrorMessage<S>.
+pub type ReadSubscriptionErrorMessage<'w, 's, S> =
+    MessageReader<'w, 's, SubscriptionErrorMessage<S>>;
+
+/// A type alias for a Bevy message reader for SubscriptionEndedMessage<C>.
+pub type ReadSubscriptionEndedMessage<'w, 's, C> =
+    MessageReader<'w, 's, SubscriptionEndedMessage<C>>;
+
+/// A type alias for a Bevy message reader for StdbTransactionMessage<C>.
+pub type ReadStdbTransactionMessage<'w, 's, C> = MessageReader<'w, 's, StdbTransactionMessage<C>>;
@@ -0,0 +1,154 @@
+use std::marker::PhantomData;
+
+use bevy::ecs::{
+    change_detection::Tick,
+    query::FilteredAccessSet,
+    system::{ReadOnlySystemParam, Res, SystemMeta, SystemParam, SystemParamValidationError},
+    world::{World, unsafe_world_cell::UnsafeWorldCell},
+};
+use spacetimedb_sdk::{__codegen as spacetime_codegen, DbContext, Table};
+
+use crate::{RegisterableTable, StdbConnection, TablePrimaryKey};
+
+/// Read-only access to the rows of the table `T` in the client cache of the connection `C`.
+///
+/// Systems using it, including run conditions, are skipped while the connection is missing or inactive.
+///
+/// ## Example
+///
+/// ```no-run
+/// fn list_players(players: StdbTable<DbConnection, PlayersTable>) {
+///     info!("{} players online", players.count());
+///     let admin = players.find(|players| players.id().find(&0));
+/// }
+/// ```
+pub struct StdbTable<'w, C, T>
+where
+    C: spacetime_codegen::DbConnection + DbContext + Send + Sync,
+    C::Module: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    T: RegisterableTable<C, C::Module>,
+{
+    conn: &'w StdbConnection<C>,
+    table: T::Table<'w>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<'w, C, T> StdbTable<'w, C, T>
+where
+    C: spacetime_codegen::DbConnection + DbContext + Send + Sync,
+    C::Module: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    T: RegisterableTable<C, C::Module>,
+{
+    /// Iterates over the rows of the table.
+    pub fn iter(&self) -> impl Iterator<Item = T::Row> + '_ {
+        self.table.iter()
+    }
+
+    /// Returns the number of rows in the table.
+    pub fn count(&self) -> u64 {
+        self.table.count()
+    }
+
+    /// Returns `true` if the table has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Looks a row up with one of the table's unique indexes, e.g. `players.find(|players| players.id().find(&id))`.
+    pub fn find(&self, lookup: impl FnOnce(&T::Table<'w>) -> Option<T::Row>) -> Option<T::Row> {
+        lookup(&self.table)
+    }
+
+    /// Returns the row with the given primary key.
+    ///
+    /// This scans every row of the table, making it O(n): prefer [`StdbTable::find`] with the primary key's
+    /// unique index for large tables.
+    pub fn get(&self, key: &<T::Message as TablePrimaryKey>::PrimaryKey) -> Option<T::Row>
+    where
+        T::Message: TablePrimaryKey,
+    {
+        self.iter().find(|row| <T::Message as TablePrimaryKey>::primary_key(row) == *key)
+    }
+
+    /// Returns the connection the table is cached by.
+    pub fn conn(&self) -> &StdbConnection<C> {
+        self.conn
+    }
+}
+
+// SAFETY: Delegates to `Res<StdbConnection<C>>`, which registers read access to the resource
+unsafe impl<C, T> SystemParam for StdbTable<'_, C, T>
+where
+    C: spacetime_codegen::DbConnection + DbContext + Send + Sync,
+    C::Module: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    T: RegisterableTable<C, C::Module> + 'static,
+{
+    type State = <Res<'static, StdbConnection<C>> as SystemParam>::State;
+    type Item<'world, 'state> = StdbTable<'world, C, T>;
+
+    fn init_state(world: &mut World) -> Self::State {
+        Res::<StdbConnection<C>>::init_state(world)
+    }
+
+    fn init_access(
+        state: &Self::State,
+        system_meta: &mut SystemMeta,
+        component_access_set: &mut FilteredAccessSet,
+        world: &mut World,
+    ) {
+        Res::<StdbConnection<C>>::init_access(state, system_meta, component_access_set, world);
+    }
+
+    #[inline]
+    unsafe fn validate_param(
+        state: &mut Self::State,
+        system_meta: &SystemMeta,
+        world: UnsafeWorldCell,
+    ) -> Result<(), SystemParamValidationError> {
+        // SAFETY: Upheld by caller
+        unsafe { Res::<StdbConnection<C>>::validate_param(state, system_meta, world) }.map_err(
+            |mut err| {
+                err.skipped = true;
+                err
+            },
+        )?;
+
+        // SAFETY: Read access to the resource is registered in init_access
+        let conn = unsafe { world.get_resource::<StdbConnection<C>>() };
+        if conn.is_some_and(|conn| conn.is_active()) {
+            Ok(())
+        } else {
+            Err(SystemParamValidationError::skipped::<Self>(
+                "The SpacetimeDB connection is not active",
+            ))
+        }
+    }
+
+    #[inline]
+    unsafe fn get_param<'world, 'state>(
+        state: &'state mut Self::State,
+        system_meta: &SystemMeta,
+        world: UnsafeWorldCell<'world>,
+        change_tick: Tick,
+    ) -> Self::Item<'world, 'state> {
+        // SAFETY: Upheld by caller
+        let conn = Res::into_inner(unsafe {
+            Res::<StdbConnection<C>>::get_param(state, system_meta, world, change_tick)
+        });
+
+        StdbTable {
+            table: T::table_accessor(conn.db()),
+            conn,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+// SAFETY: Only reads the StdbConnection resource
+unsafe impl<C, T> ReadOnlySystemParam for StdbTable<'_, C, T>
+where
+    C: spacetime_codegen::DbConnection + DbContext + Send + Sync,
+    C::Module: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    T: RegisterableTable<C, C::Module> + 'static,
+{
+}
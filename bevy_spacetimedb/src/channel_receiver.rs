@@ -9,6 +9,12 @@ use std::sync::mpsc::Receiver;
 #[derive(Resource, Deref, DerefMut)]
 struct ChannelReceiver<T>(Mutex<Receiver<T>>);
 
+/// The [`PreUpdate`] system set in which channel messages are forwarded into Bevy.
+///
+/// Order your systems after this set to read messages in the same frame they were received.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageChannelSystems;
+
 /// Allows to register a message channel backed by a `mpsc::Receiver<T>`.
 /// This is useful in multithreaded applications where you want to send messages from a different thread
 pub trait AddMessageChannelAppExtensions {
@@ -24,7 +30,7 @@ impl AddMessageChannelAppExtensions for App {
         );
 
         self.add_message::<T>();
-        self.add_systems(PreUpdate, channel_to_message::<T>);
+        self.add_systems(PreUpdate, channel_to_message::<T>.in_set(MessageChannelSystems));
         self.insert_resource(ChannelReceiver(Mutex::new(receiver)));
         self
     }
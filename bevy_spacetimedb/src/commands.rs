@@ -0,0 +1,137 @@
+use bevy::prelude::{Commands, World};
+use spacetimedb_sdk::{DbContext, SubscriptionBuilder};
+
+use crate::{
+    StdbPluginConfig, StdbSubscriptionId, StdbSubscriptions,
+    plugin::{disconnect, try_connect},
+    subscriptions::subscribe,
+};
+
+/// Allows to manage the SpacetimeDB connection at runtime through [`Commands`].
+///
+/// The connection is rebuilt from the [`StdbPluginConfig`] resource, whose `uri` and `module_name`
+/// can be changed beforehand to switch servers.
+pub trait StdbCommandsExt {
+    /// Connect to SpacetimeDB with the given token, replacing the current connection if there is one.
+    ///
+    /// If the connection can't be built, a [`StdbConnectionErrorMessage`](crate::StdbConnectionErrorMessage) is emitted.
+    fn stdb_connect<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+        token: Option<String>,
+    );
+
+    /// Close the connection and remove the [`StdbConnection`](crate::StdbConnection) resource,
+    /// which frees the client cache and every table callback registered on it.
+    ///
+    /// Automatic reconnection is suspended until the next connection.
+    fn stdb_disconnect<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+    );
+
+    /// Close the connection and connect again with the token of the last successful connection.
+    fn stdb_reconnect<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+    );
+
+    /// Subscribe to the given queries on the current connection, returning the id of the subscription.
+    ///
+    /// The subscription is tracked by the [`StdbSubscriptions`] resource, which emits
+    /// [`SubscriptionAppliedMessage<C>`](crate::SubscriptionAppliedMessage),
+    /// [`SubscriptionErrorMessage<C>`](crate::SubscriptionErrorMessage) and
+    /// [`SubscriptionEndedMessage<C>`](crate::SubscriptionEndedMessage) with this id.
+    /// It ends when the connection is closed and is not renewed on reconnection.
+    fn stdb_subscribe<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M>
+            + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>
+            + Send
+            + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+        queries: impl IntoIterator<Item = impl Into<String>>,
+    ) -> StdbSubscriptionId;
+
+    /// Unsubscribe from a subscription made with [`StdbCommandsExt::stdb_subscribe`].
+    ///
+    /// A pending subscription is unsubscribed as soon as it is applied.
+    fn stdb_unsubscribe<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+        id: StdbSubscriptionId,
+    );
+}
+
+impl StdbCommandsExt for Commands<'_, '_> {
+    fn stdb_connect<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+        token: Option<String>,
+    ) {
+        self.queue(move |world: &mut World| {
+            world.resource_mut::<StdbPluginConfig<C, M>>().token = token.clone();
+            try_connect::<C, M>(world, token);
+        });
+    }
+
+    fn stdb_disconnect<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+    ) {
+        self.queue(disconnect::<C, M>);
+    }
+
+    fn stdb_reconnect<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+    ) {
+        self.queue(|world: &mut World| {
+            let token = world.resource::<StdbPluginConfig<C, M>>().token.clone();
+            try_connect::<C, M>(world, token);
+        });
+    }
+
+    fn stdb_subscribe<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M>
+            + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>
+            + Send
+            + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+        queries: impl IntoIterator<Item = impl Into<String>>,
+    ) -> StdbSubscriptionId {
+        let id = StdbSubscriptionId::next();
+        let queries: Vec<String> = queries.into_iter().map(Into::into).collect();
+        self.queue(move |world: &mut World| subscribe::<C, M>(world, id, queries));
+        id
+    }
+
+    fn stdb_unsubscribe<
+        C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+        M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+    >(
+        &mut self,
+        id: StdbSubscriptionId,
+    ) {
+        self.queue(move |world: &mut World| {
+            world.resource_mut::<StdbSubscriptions<C>>().unsubscribe(id);
+        });
+    }
+}
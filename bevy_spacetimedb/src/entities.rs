@@ -0,0 +1,446 @@
+use std::{
+    hash::Hash,
+    ops::{Deref, DerefMut},
+};
+
+use bevy::{
+    app::{App, PreUpdate},
+    platform::collections::HashMap,
+    ecs::system::EntityCommands,
+    prelude::{Commands, Component, Entity, IntoScheduleConfigs, Res, ResMut, Resource},
+};
+use spacetimedb_sdk::__codegen as spacetime_codegen;
+
+use crate::{
+    MessageChannelSystems, ReadDeleteMessage, ReadInsertMessage, ReadStdbConnectedMessage,
+    ReadUpdateMessage, RegisterableTable, StdbPlugin, StdbSequence, TableMessage,
+};
+
+/// Gives access to the primary key of a table's rows, needed to mirror them as entities
+/// with [`StdbPlugin::add_table_as_entities`].
+///
+/// ## Example
+///
+/// ```no-run
+/// impl TablePrimaryKey for PlayersTable {
+///     type PrimaryKey = u64;
+///
+///     fn primary_key(row: &Player) -> u64 {
+///         row.id
+///     }
+/// }
+/// ```
+pub trait TablePrimaryKey: TableMessage + Send + Sync + 'static {
+    type PrimaryKey: Eq + Hash + Clone + Send + Sync + 'static;
+
+    fn primary_key(row: &Self::Row) -> Self::PrimaryKey;
+}
+
+/// Projects the rows of a table onto Bevy components, for the entities mirroring them
+/// with [`StdbPlugin::add_table_with_components`].
+///
+/// Can be derived with `#[derive(StdbRowToComponents)]` when the `macros` feature is enabled.
+/// The entity may be despawned by the time the commands are applied, so use `try_insert`.
+///
+/// ## Example
+///
+/// ```no-run
+/// impl StdbRowToComponents for PlayersTable {
+///     fn insert_components(row: &Player, entity: &mut EntityCommands) {
+///         entity.try_insert((Transform::from_xyz(row.x, row.y, row.z), Name::new(row.name.clone())));
+///     }
+///
+///     fn update_components(old: &Player, new: &Player, entity: &mut EntityCommands) {
+///         if (old.x, old.y, old.z) != (new.x, new.y, new.z) {
+///             entity.try_insert(Transform::from_xyz(new.x, new.y, new.z));
+///         }
+///         if old.name != new.name {
+///             entity.try_insert(Name::new(new.name.clone()));
+///         }
+///     }
+/// }
+/// ```
+pub trait StdbRowToComponents: TablePrimaryKey {
+    /// Inserts every component projected from the row.
+    fn insert_components(row: &Self::Row, entity: &mut EntityCommands);
+
+    /// Re-inserts only the components whose fields changed, so `Changed<...>` queries see real changes.
+    fn update_components(old: &Self::Row, new: &Self::Row, entity: &mut EntityCommands);
+}
+
+/// The row mirrored by an entity spawned with [`StdbPlugin::add_table_as_entities`].
+#[derive(Component)]
+pub struct StdbRow<T: TableMessage + Send + Sync + 'static> {
+    pub row: T::Row,
+}
+
+impl<T: TableMessage + Send + Sync + 'static> Deref for StdbRow<T> {
+    type Target = T::Row;
+
+    fn deref(&self) -> &Self::Target {
+        &self.row
+    }
+}
+
+impl<T: TableMessage + Send + Sync + 'static> DerefMut for StdbRow<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.row
+    }
+}
+
+/// Maps the primary key of the rows mirrored with [`StdbPlugin::add_table_as_entities`] to their entity.
+#[derive(Resource)]
+pub struct StdbEntityMap<T: TablePrimaryKey> {
+    entities: HashMap<T::PrimaryKey, Entity>,
+}
+
+impl<T: TablePrimaryKey> Default for StdbEntityMap<T> {
+    fn default() -> Self {
+        Self {
+            entities: HashMap::default(),
+        }
+    }
+}
+
+impl<T: TablePrimaryKey> StdbEntityMap<T> {
+    /// Returns the entity mirroring the row with the given primary key.
+    pub fn get(&self, key: &T::PrimaryKey) -> Option<Entity> {
+        self.entities.get(key).copied()
+    }
+
+    /// Iterates over the primary keys and their entity.
+    pub fn iter(&self) -> impl Iterator<Item = (&T::PrimaryKey, Entity)> {
+        self.entities.iter().map(|(key, entity)| (key, *entity))
+    }
+
+    /// Returns the number of mirrored rows.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Returns `true` if no row is mirrored.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl<
+    C: spacetime_codegen::DbConnection<Module = M> + spacetimedb_sdk::DbContext + Send + Sync + 'static,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+> StdbPlugin<C, M>
+{
+    /// Registers a table like [`StdbPlugin::add_table`], and keeps one entity per row with the row
+    /// as a [`StdbRow<T>`] component, updated in place and despawned along with the row.
+    ///
+    /// The entities can be looked up by primary key with the [`StdbEntityMap<T>`] resource.
+    /// Once the connection is closed, the entities are kept until a new connection is established:
+    /// they are despawned then, and spawned again as the new connection receives the rows.
+    pub fn add_table_as_entities<T: RegisterableTable<C, M> + Send + Sync + 'static>(self) -> Self
+    where
+        T::Message: TablePrimaryKey,
+    {
+        let plugin = self.add_table::<T>();
+
+        // This callback adds the system mirroring the rows when the plugin is built.
+        let register_fn = |app: &mut App| {
+            app.init_resource::<StdbEntityMap<T::Message>>().add_systems(
+                PreUpdate,
+                mirror_rows::<C, T::Message>.after(MessageChannelSystems),
+            );
+        };
+
+        // The register_fn will get called once, when the plugin is built.
+        plugin.system_registers.lock().unwrap().push(Box::new(register_fn));
+
+        plugin
+    }
+
+    /// Mirrors a table as entities like [`StdbPlugin::add_table_as_entities`], and projects
+    /// the rows onto components with [`StdbRowToComponents`].
+    pub fn add_table_with_components<T: RegisterableTable<C, M> + Send + Sync + 'static>(
+        self,
+    ) -> Self
+    where
+        T::Message: StdbRowToComponents,
+    {
+        let plugin = self.add_table_as_entities::<T>();
+
+        // This callback adds the system projecting the rows when the plugin is built.
+        let register_fn = |app: &mut App| {
+            app.add_systems(
+                PreUpdate,
+                project_rows::<T::Message>.after(mirror_rows::<C, T::Message>),
+            );
+        };
+
+        // The register_fn will get called once, when the plugin is built.
+        plugin.system_registers.lock().unwrap().push(Box::new(register_fn));
+
+        plugin
+    }
+}
+
+/// A change to the mirrored rows, applied in the order the plugin received it.
+enum RowChange<'a, R> {
+    Upsert(&'a R),
+    Delete(&'a R),
+    /// A new connection was established, its subscriptions send the rows again.
+    Reconnect,
+}
+
+/// Spawns, updates and despawns the entities mirroring the rows of the table `T`.
+fn mirror_rows<C: Send + Sync + 'static, T: TablePrimaryKey>(
+    mut commands: Commands,
+    mut entities: ResMut<StdbEntityMap<T>>,
+    mut connected: ReadStdbConnectedMessage<C>,
+    mut inserts: ReadInsertMessage<T>,
+    mut updates: ReadUpdateMessage<T>,
+    mut deletes: ReadDeleteMessage<T>,
+) {
+    let entities = &mut entities.entities;
+
+    // A row deleted then inserted again, possibly by a later transaction, must end up spawned
+    let mut changes: Vec<(StdbSequence, RowChange<T::Row>)> = connected
+        .read()
+        .map(|message| (message.sequence, RowChange::Reconnect))
+        .chain(inserts.read().map(|message| (message.sequence, RowChange::Upsert(&message.row))))
+        // The primary key can't change, a row with a new one is inserted instead
+        .chain(updates.read().map(|message| (message.sequence, RowChange::Upsert(&message.new))))
+        .chain(deletes.read().map(|message| (message.sequence, RowChange::Delete(&message.row))))
+        .collect();
+    changes.sort_by_key(|(sequence, _)| *sequence);
+
+    for (_, change) in changes {
+        match change {
+            RowChange::Upsert(row) => upsert::<T>(&mut commands, entities, row),
+            RowChange::Delete(row) => {
+                if let Some(entity) = entities.remove(&T::primary_key(row)) {
+                    commands.entity(entity).try_despawn();
+                }
+            }
+            // The rows the new connection doesn't send again would never be deleted
+            RowChange::Reconnect => {
+                for (_, entity) in entities.drain() {
+                    commands.entity(entity).try_despawn();
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the row of the entity mirroring it, or spawns one.
+fn upsert<T: TablePrimaryKey>(
+    commands: &mut Commands,
+    entities: &mut HashMap<T::PrimaryKey, Entity>,
+    row: &T::Row,
+) {
+    let row = StdbRow::<T> { row: row.clone() };
+
+    match entities.get(&T::primary_key(&row)) {
+        Some(entity) => {
+            commands.entity(*entity).try_insert(row);
+        }
+        None => {
+            let key = T::primary_key(&row);
+            entities.insert(key, commands.spawn(row).id());
+        }
+    }
+}
+
+/// A change to the projected rows, applied in the order the plugin received it.
+enum RowProjection<'a, R> {
+    Insert(&'a R),
+    Update(&'a R, &'a R),
+}
+
+/// Inserts and updates the components projected from the rows of the table `T`,
+/// on the entities spawned by [`mirror_rows`].
+fn project_rows<T: StdbRowToComponents>(
+    mut commands: Commands,
+    entities: Res<StdbEntityMap<T>>,
+    mut inserts: ReadInsertMessage<T>,
+    mut updates: ReadUpdateMessage<T>,
+) {
+    // An update projected after a later insertion of the same row would leave stale components
+    let mut changes: Vec<(StdbSequence, RowProjection<T::Row>)> = inserts
+        .read()
+        .map(|message| (message.sequence, RowProjection::Insert(&message.row)))
+        .chain(updates.read().map(|message| {
+            (message.sequence, RowProjection::Update(&message.old, &message.new))
+        }))
+        .collect();
+    changes.sort_by_key(|(sequence, _)| *sequence);
+
+    for (_, change) in changes {
+        let row = match change {
+            RowProjection::Insert(row) | RowProjection::Update(_, row) => row,
+        };
+        // The row may have been deleted since
+        let Some(entity) = entities.get(&T::primary_key(row)) else {
+            continue;
+        };
+        match change {
+            RowProjection::Insert(row) => T::insert_components(row, &mut commands.entity(entity)),
+            RowProjection::Update(old, new) => {
+                T::update_components(old, new, &mut commands.entity(entity))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use spacetimedb_sdk::{Event, Identity};
+
+    use super::*;
+    use crate::{DeleteMessage, InsertMessage, StdbConnectedMessage, StdbTransactionId, UpdateMessage};
+
+    struct TestConnection;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Position {
+        entity: u32,
+        x: i32,
+    }
+
+    struct PositionsTable;
+
+    impl TableMessage for PositionsTable {
+        type Row = Position;
+        type Reducer = ();
+    }
+
+    impl TablePrimaryKey for PositionsTable {
+        type PrimaryKey = u32;
+
+        fn primary_key(row: &Position) -> u32 {
+            row.entity
+        }
+    }
+
+    #[derive(Component, Debug, PartialEq)]
+    struct X(i32);
+
+    impl StdbRowToComponents for PositionsTable {
+        fn insert_components(row: &Position, entity: &mut EntityCommands) {
+            entity.try_insert(X(row.x));
+        }
+
+        fn update_components(old: &Position, new: &Position, entity: &mut EntityCommands) {
+            if old.x != new.x {
+                entity.try_insert(X(new.x));
+            }
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_message::<StdbConnectedMessage<TestConnection>>()
+            .add_message::<InsertMessage<PositionsTable>>()
+            .add_message::<UpdateMessage<PositionsTable>>()
+            .add_message::<DeleteMessage<PositionsTable>>()
+            .init_resource::<StdbEntityMap<PositionsTable>>()
+            .add_systems(
+                PreUpdate,
+                (
+                    mirror_rows::<TestConnection, PositionsTable>,
+                    project_rows::<PositionsTable>,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn insert(app: &mut App, entity: u32, x: i32) {
+        app.world_mut().write_message(InsertMessage::<PositionsTable> {
+            sequence: StdbSequence::next(),
+            event: Event::UnknownTransaction,
+            transaction: StdbTransactionId(0),
+            row: Position { entity, x },
+        });
+    }
+
+    fn update(app: &mut App, entity: u32, old_x: i32, new_x: i32) {
+        app.world_mut().write_message(UpdateMessage::<PositionsTable> {
+            sequence: StdbSequence::next(),
+            event: Event::UnknownTransaction,
+            transaction: StdbTransactionId(0),
+            old: Position { entity, x: old_x },
+            new: Position { entity, x: new_x },
+        });
+    }
+
+    fn delete(app: &mut App, entity: u32, x: i32) {
+        app.world_mut().write_message(DeleteMessage::<PositionsTable> {
+            sequence: StdbSequence::next(),
+            event: Event::UnknownTransaction,
+            transaction: StdbTransactionId(0),
+            row: Position { entity, x },
+        });
+    }
+
+    /// Returns the row and projected component of every mirrored entity.
+    fn mirrored(app: &mut App) -> Vec<(Position, Option<i32>)> {
+        let mut query = app
+            .world_mut()
+            .query::<(&StdbRow<PositionsTable>, Option<&X>)>();
+        query
+            .iter(app.world())
+            .map(|(row, x)| (row.row.clone(), x.map(|x| x.0)))
+            .collect()
+    }
+
+    #[test]
+    fn a_row_deleted_then_inserted_again_in_one_frame_is_respawned() {
+        let mut app = app();
+        insert(&mut app, 1, 10);
+        app.update();
+        let first = app.world().resource::<StdbEntityMap<PositionsTable>>().get(&1);
+
+        delete(&mut app, 1, 10);
+        insert(&mut app, 1, 20);
+        app.update();
+
+        assert_eq!(mirrored(&mut app), vec![(Position { entity: 1, x: 20 }, Some(20))]);
+        let map = app.world().resource::<StdbEntityMap<PositionsTable>>();
+        assert_eq!(map.len(), 1);
+        assert_ne!(map.get(&1), first);
+    }
+
+    #[test]
+    fn components_follow_the_order_of_the_changes() {
+        let mut app = app();
+        insert(&mut app, 1, 10);
+        app.update();
+
+        // The update must not be projected over the row inserted after it
+        update(&mut app, 1, 10, 15);
+        delete(&mut app, 1, 15);
+        insert(&mut app, 1, 20);
+        app.update();
+
+        assert_eq!(mirrored(&mut app), vec![(Position { entity: 1, x: 20 }, Some(20))]);
+    }
+
+    #[test]
+    fn a_new_connection_respawns_the_rows() {
+        let mut app = app();
+        insert(&mut app, 1, 10);
+        insert(&mut app, 2, 20);
+        app.update();
+
+        app.world_mut().write_message(StdbConnectedMessage::<TestConnection> {
+            sequence: StdbSequence::next(),
+            identity: Identity::ZERO,
+            access_token: String::new(),
+            generation: 0,
+            _phantom: PhantomData,
+        });
+        insert(&mut app, 2, 21);
+        app.update();
+
+        assert_eq!(mirrored(&mut app), vec![(Position { entity: 2, x: 21 }, Some(21))]);
+    }
+}
@@ -0,0 +1,425 @@
+use std::sync::{Arc, Mutex, mpsc::Sender};
+
+use bevy::{app::App, platform::collections::HashSet};
+use spacetimedb_sdk::{__codegen as spacetime_codegen, Event, Table, TableWithPrimaryKey};
+
+use crate::{
+    DeleteMessage, InsertMessage, InsertUpdateMessage, RegisterableTable, StdbPlugin, StdbSequence,
+    StdbTransactionId, TableMessages, TablePrimaryKey, UpdateMessage,
+    plugin::{MessageSenders, message_sender},
+    tables::register_table_changes,
+    transactions::Transactions,
+};
+
+/// Decides which rows of a table are forwarded to Bevy, see [`StdbPlugin::add_filtered_table`].
+type RowFilter<R> = Arc<dyn Fn(&R) -> bool + Send + Sync>;
+
+/// Forwards the rows passing the filter of a table registered with [`StdbPlugin::add_filtered_table`].
+///
+/// The forwarded rows are tracked by primary key, so they are deleted from Bevy even if they would
+/// not pass the filter anymore.
+struct FilteredRows<T: TablePrimaryKey> {
+    filter: RowFilter<T::Row>,
+    forwarded: Mutex<HashSet<T::PrimaryKey>>,
+    send_insert: Option<Sender<InsertMessage<T>>>,
+    send_delete: Option<Sender<DeleteMessage<T>>>,
+    send_update: Option<Sender<UpdateMessage<T>>>,
+    send_insert_update: Option<Sender<InsertUpdateMessage<T>>>,
+}
+
+impl<T: TablePrimaryKey> FilteredRows<T> {
+    fn insert(&self, transactions: &Transactions, event: Event<T::Reducer>, row: &T::Row) {
+        if !(self.filter)(row) {
+            return;
+        }
+        self.forwarded.lock().unwrap().insert(T::primary_key(row));
+        self.send_inserted(transactions, event, row);
+    }
+
+    fn delete(&self, transactions: &Transactions, event: Event<T::Reducer>, row: &T::Row) {
+        if self.forwarded.lock().unwrap().remove(&T::primary_key(row)) {
+            self.send_deleted(transactions, event, row);
+        }
+    }
+
+    fn update(
+        &self,
+        transactions: &Transactions,
+        event: Event<T::Reducer>,
+        old: &T::Row,
+        new: &T::Row,
+    ) {
+        let passes = (self.filter)(new);
+        let forwarded = {
+            let mut forwarded = self.forwarded.lock().unwrap();
+            // The primary key can't change, a row with a new one is inserted instead
+            if passes {
+                !forwarded.insert(T::primary_key(new))
+            } else {
+                forwarded.remove(&T::primary_key(old))
+            }
+        };
+
+        match (forwarded, passes) {
+            (true, true) => {
+                let (update_old, update_new) = (old.clone(), new.clone());
+                send(
+                    transactions,
+                    event.clone(),
+                    &self.send_update,
+                    |sequence, event, transaction| UpdateMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        old: update_old,
+                        new: update_new,
+                    },
+                );
+                let (old, new) = (old.clone(), new.clone());
+                send(
+                    transactions,
+                    event,
+                    &self.send_insert_update,
+                    |sequence, event, transaction| InsertUpdateMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        old: Some(old),
+                        new,
+                    },
+                );
+            }
+            // A row starting to pass the filter is sent as an insertion, and one stopping as a deletion
+            (false, true) => self.send_inserted(transactions, event, new),
+            (true, false) => self.send_deleted(transactions, event, old),
+            (false, false) => {}
+        }
+    }
+
+    fn send_inserted(&self, transactions: &Transactions, event: Event<T::Reducer>, row: &T::Row) {
+        let inserted = row.clone();
+        send(
+            transactions,
+            event.clone(),
+            &self.send_insert,
+            |sequence, event, transaction| InsertMessage {
+                sequence,
+                event,
+                transaction,
+                row: inserted,
+            },
+        );
+        let row = row.clone();
+        send(
+            transactions,
+            event,
+            &self.send_insert_update,
+            |sequence, event, transaction| InsertUpdateMessage {
+                sequence,
+                event,
+                transaction,
+                old: None,
+                new: row,
+            },
+        );
+    }
+
+    fn send_deleted(&self, transactions: &Transactions, event: Event<T::Reducer>, row: &T::Row) {
+        let row = row.clone();
+        send(
+            transactions,
+            event,
+            &self.send_delete,
+            |sequence, event, transaction| DeleteMessage {
+                sequence,
+                event,
+                transaction,
+                row,
+            },
+        );
+    }
+}
+
+/// Sends the message built by `message` if it is registered, like the unfiltered table callbacks do.
+fn send<R: Send + 'static, E: Send + 'static>(
+    transactions: &Transactions,
+    event: Event<R>,
+    sender: &Option<Sender<E>>,
+    message: impl FnOnce(StdbSequence, Event<R>, StdbTransactionId) -> E + Send + 'static,
+) {
+    let Some(sender) = sender.clone() else {
+        return;
+    };
+    transactions.send(event, |event, transaction| {
+        let sequence = StdbSequence::next();
+        Box::new(move || {
+            let _ = sender.send(message(sequence, event, transaction));
+        })
+    });
+}
+
+impl<
+    C: spacetime_codegen::DbConnection<Module = M> + spacetimedb_sdk::DbContext,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+> StdbPlugin<C, M>
+{
+    /// Registers a table for the bevy application with all messages enabled, only forwarding the rows
+    /// passing `filter`, e.g. the entities within view distance or owned by the player.
+    ///
+    /// The filter runs on the thread running the connection, before the rows are cloned and sent to Bevy.
+    /// It is evaluated when a row is inserted or updated: an update is sent as an [`InsertMessage`] when the row
+    /// starts passing the filter, and as a [`DeleteMessage`] when it stops passing it.
+    /// The forwarded rows are tracked by primary key, so their updates and deletions are always forwarded,
+    /// even if the filter depends on state that changed since. A row is only filtered again when it changes.
+    pub fn add_filtered_table<T: RegisterableTable<C, M> + Send + Sync + 'static>(
+        self,
+        filter: impl Fn(&T::Row) -> bool + Send + Sync + 'static,
+    ) -> Self
+    where
+        T::Message: TablePrimaryKey,
+    {
+        self.add_partial_filtered_table::<T>(TableMessages::all(), filter)
+    }
+
+    /// Registers a table like [`StdbPlugin::add_filtered_table`], with the specified messages in the `messages` parameter.
+    pub fn add_partial_filtered_table<T: RegisterableTable<C, M> + Send + Sync + 'static>(
+        self,
+        messages: TableMessages,
+        filter: impl Fn(&T::Row) -> bool + Send + Sync + 'static,
+    ) -> Self
+    where
+        T::Message: TablePrimaryKey,
+    {
+        if messages.insert {
+            self.register_channel::<InsertMessage<T::Message>>();
+        }
+        if messages.delete {
+            self.register_channel::<DeleteMessage<T::Message>>();
+        }
+        if messages.update {
+            self.register_channel::<UpdateMessage<T::Message>>();
+        }
+        if messages.update && messages.insert {
+            self.register_channel::<InsertUpdateMessage<T::Message>>();
+        }
+
+        // A closure that sets up messages for the table
+        let filter: RowFilter<T::Row> = Arc::new(filter);
+        let transactions = Arc::clone(&self.transactions);
+        let register = move |senders: &MessageSenders, db: &C::DbView| {
+            // The rows are sent again by every new connection
+            let rows = Arc::new(FilteredRows::<T::Message> {
+                filter: Arc::clone(&filter),
+                forwarded: Mutex::new(HashSet::default()),
+                send_insert: messages.insert.then(|| message_sender(senders)),
+                send_delete: messages.delete.then(|| message_sender(senders)),
+                send_update: messages.update.then(|| message_sender(senders)),
+                send_insert_update: (messages.update && messages.insert)
+                    .then(|| message_sender(senders)),
+            });
+            Self::on_filtered_change::<T>(&rows, &transactions, db);
+        };
+
+        // Store this table, and call it on every new connection.
+        self.table_registers
+            .lock()
+            .unwrap()
+            .push(Box::new(register));
+
+        self.system_registers
+            .lock()
+            .unwrap()
+            .push(Box::new(move |app: &mut App| {
+                register_table_changes::<T::Message>(
+                    app,
+                    messages.insert,
+                    messages.update,
+                    messages.delete,
+                );
+            }));
+
+        self
+    }
+
+    /// Forwards the rows inserted, updated and deleted in the provided table through [`FilteredRows`].
+    fn on_filtered_change<T: RegisterableTable<C, M> + Send + Sync + 'static>(
+        rows: &Arc<FilteredRows<T::Message>>,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) where
+        T::Message: TablePrimaryKey,
+    {
+        let (insert_rows, insert_transactions) = (Arc::clone(rows), Arc::clone(transactions));
+        T::table_accessor(db).on_insert(move |ctx, row| {
+            insert_rows.insert(&insert_transactions, T::context_event_accessor(ctx), row);
+        });
+
+        let (delete_rows, delete_transactions) = (Arc::clone(rows), Arc::clone(transactions));
+        T::table_accessor(db).on_delete(move |ctx, row| {
+            delete_rows.delete(&delete_transactions, T::context_event_accessor(ctx), row);
+        });
+
+        let (rows, transactions) = (Arc::clone(rows), Arc::clone(transactions));
+        T::table_accessor(db).on_update(move |ctx, old, new| {
+            rows.update(&transactions, T::context_event_accessor(ctx), old, new);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{Receiver, channel};
+
+    use super::*;
+    use crate::TableMessage;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Position {
+        entity: u32,
+        x: i32,
+    }
+
+    struct PositionsTable;
+
+    impl TableMessage for PositionsTable {
+        type Row = Position;
+        type Reducer = ();
+    }
+
+    impl TablePrimaryKey for PositionsTable {
+        type PrimaryKey = u32;
+
+        fn primary_key(row: &Position) -> u32 {
+            row.entity
+        }
+    }
+
+    struct Received {
+        inserts: Receiver<InsertMessage<PositionsTable>>,
+        deletes: Receiver<DeleteMessage<PositionsTable>>,
+        updates: Receiver<UpdateMessage<PositionsTable>>,
+        insert_updates: Receiver<InsertUpdateMessage<PositionsTable>>,
+    }
+
+    /// Forwards the positions within view distance, `x < 100`.
+    fn filtered_rows() -> (FilteredRows<PositionsTable>, Received) {
+        let (send_insert, inserts) = channel();
+        let (send_delete, deletes) = channel();
+        let (send_update, updates) = channel();
+        let (send_insert_update, insert_updates) = channel();
+        let rows = FilteredRows {
+            filter: Arc::new(|position: &Position| position.x < 100),
+            forwarded: Mutex::new(HashSet::default()),
+            send_insert: Some(send_insert),
+            send_delete: Some(send_delete),
+            send_update: Some(send_update),
+            send_insert_update: Some(send_insert_update),
+        };
+        let received = Received {
+            inserts,
+            deletes,
+            updates,
+            insert_updates,
+        };
+        (rows, received)
+    }
+
+    fn position(entity: u32, x: i32) -> Position {
+        Position { entity, x }
+    }
+
+    #[test]
+    fn an_update_into_the_filter_is_sent_as_an_insert() {
+        let (rows, received) = filtered_rows();
+        let transactions = Transactions::default();
+        rows.insert(&transactions, Event::UnknownTransaction, &position(1, 150));
+        assert!(received.inserts.try_recv().is_err());
+
+        rows.update(
+            &transactions,
+            Event::UnknownTransaction,
+            &position(1, 150),
+            &position(1, 50),
+        );
+
+        let inserts: Vec<_> = received.inserts.try_iter().map(|m| m.row).collect();
+        assert_eq!(inserts, vec![position(1, 50)]);
+        let insert_updates: Vec<_> = received.insert_updates.try_iter().collect();
+        assert_eq!(insert_updates.len(), 1);
+        assert_eq!(insert_updates[0].old, None);
+        assert!(received.updates.try_recv().is_err());
+
+        // The row is forwarded from now on
+        rows.delete(&transactions, Event::UnknownTransaction, &position(1, 50));
+        let deletes: Vec<_> = received.deletes.try_iter().map(|m| m.row).collect();
+        assert_eq!(deletes, vec![position(1, 50)]);
+    }
+
+    #[test]
+    fn an_update_out_of_the_filter_is_sent_as_a_delete() {
+        let (rows, received) = filtered_rows();
+        let transactions = Transactions::default();
+        rows.insert(&transactions, Event::UnknownTransaction, &position(1, 50));
+        assert_eq!(received.inserts.try_iter().count(), 1);
+
+        rows.update(
+            &transactions,
+            Event::UnknownTransaction,
+            &position(1, 50),
+            &position(1, 150),
+        );
+
+        // The row is deleted as Bevy last saw it
+        let deletes: Vec<_> = received.deletes.try_iter().map(|m| m.row).collect();
+        assert_eq!(deletes, vec![position(1, 50)]);
+        assert!(received.updates.try_recv().is_err());
+
+        // The row is not forwarded anymore
+        rows.update(
+            &transactions,
+            Event::UnknownTransaction,
+            &position(1, 150),
+            &position(1, 160),
+        );
+        rows.delete(&transactions, Event::UnknownTransaction, &position(1, 160));
+        assert!(received.updates.try_recv().is_err());
+        assert!(received.deletes.try_recv().is_err());
+    }
+
+    #[test]
+    fn an_update_within_the_filter_is_sent_as_an_update() {
+        let (rows, received) = filtered_rows();
+        let transactions = Transactions::default();
+        rows.insert(&transactions, Event::UnknownTransaction, &position(1, 50));
+        received.insert_updates.try_iter().for_each(drop);
+
+        rows.update(
+            &transactions,
+            Event::UnknownTransaction,
+            &position(1, 50),
+            &position(1, 60),
+        );
+
+        let updates: Vec<_> = received.updates.try_iter().collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].old, position(1, 50));
+        assert_eq!(updates[0].new, position(1, 60));
+        let insert_updates: Vec<_> = received.insert_updates.try_iter().collect();
+        assert_eq!(insert_updates.len(), 1);
+        assert_eq!(insert_updates[0].old, Some(position(1, 50)));
+    }
+
+    #[test]
+    fn deleting_a_row_that_was_never_forwarded_sends_nothing() {
+        let (rows, received) = filtered_rows();
+        let transactions = Transactions::default();
+        rows.insert(&transactions, Event::UnknownTransaction, &position(1, 150));
+
+        rows.delete(&transactions, Event::UnknownTransaction, &position(1, 150));
+        rows.delete(&transactions, Event::UnknownTransaction, &position(2, 50));
+
+        assert!(received.inserts.try_recv().is_err());
+        assert!(received.deletes.try_recv().is_err());
+        assert!(received.insert_updates.try_recv().is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    collections::VecDeque,
+    hash::Hash,
+    sync::{Arc, Mutex, mpsc::Sender},
+};
+
+use bevy::{app::App, platform::collections::HashMap};
+use spacetimedb_sdk::{__codegen as spacetime_codegen, Event, Table};
+
+use crate::{
+    DeleteMessage, InsertMessage, InsertUpdateMessage, RegisterableTableWithoutPk, StdbPlugin,
+    StdbSequence, StdbTransactionId, TableMessage, UpdateMessage,
+    plugin::{MessageSenders, message_sender},
+    tables::register_table_changes,
+    transactions::Transactions,
+};
+
+/// Extracts the key telling apart the rows of a table without primary key.
+type RowKeyFn<T, K> = Arc<dyn Fn(&<T as TableMessage>::Row) -> K + Send + Sync>;
+
+#[derive(Clone, Copy)]
+enum RowChangeKind {
+    Insert,
+    Delete,
+}
+
+/// A row inserted or deleted in a table without primary key, waiting for its transaction to complete.
+struct RowChange<T: TableMessage> {
+    sequence: StdbSequence,
+    event: Event<T::Reducer>,
+    transaction: StdbTransactionId,
+    kind: RowChangeKind,
+    row: T::Row,
+}
+
+/// Pairs the rows deleted and inserted with the same key in a transaction into updates,
+/// for a table registered with [`StdbPlugin::add_table_without_pk_with_key`].
+struct RowPairs<T: TableMessage, K> {
+    key: RowKeyFn<T, K>,
+    changes: Mutex<Vec<RowChange<T>>>,
+    send_insert: Sender<InsertMessage<T>>,
+    send_delete: Sender<DeleteMessage<T>>,
+    send_update: Sender<UpdateMessage<T>>,
+    send_insert_update: Sender<InsertUpdateMessage<T>>,
+}
+
+impl<T: TableMessage, K: Eq + Hash> RowPairs<T, K> {
+    /// Sends the messages of the given transaction, once it is complete.
+    ///
+    /// Every row of the transaction calls it, only the first call finds rows to send.
+    fn flush(&self, transaction: StdbTransactionId) {
+        let changes: Vec<_> = {
+            let mut changes = self.changes.lock().unwrap();
+            let (flushed, kept) = changes
+                .drain(..)
+                .partition(|change| change.transaction == transaction);
+            *changes = kept;
+            flushed
+        };
+
+        let mut deleted: HashMap<K, VecDeque<RowChange<T>>> = HashMap::default();
+        let mut inserted = Vec::new();
+        for change in changes {
+            match change.kind {
+                RowChangeKind::Delete => deleted
+                    .entry((self.key)(&change.row))
+                    .or_default()
+                    .push_back(change),
+                RowChangeKind::Insert => inserted.push(change),
+            }
+        }
+
+        for new in inserted {
+            // An inserted row replaces the first row deleted with the same key
+            let old = deleted
+                .get_mut(&(self.key)(&new.row))
+                .and_then(VecDeque::pop_front)
+                .map(|old| old.row);
+
+            match &old {
+                Some(old) => {
+                    let _ = self.send_update.send(UpdateMessage {
+                        sequence: new.sequence,
+                        event: new.event.clone(),
+                        transaction,
+                        old: old.clone(),
+                        new: new.row.clone(),
+                    });
+                }
+                None => {
+                    let _ = self.send_insert.send(InsertMessage {
+                        sequence: new.sequence,
+                        event: new.event.clone(),
+                        transaction,
+                        row: new.row.clone(),
+                    });
+                }
+            }
+            let _ = self.send_insert_update.send(InsertUpdateMessage {
+                sequence: new.sequence,
+                event: new.event,
+                transaction,
+                old,
+                new: new.row,
+            });
+        }
+
+        let mut deleted: Vec<_> = deleted.into_values().flatten().collect();
+        deleted.sort_by_key(|change| change.sequence);
+        for change in deleted {
+            let _ = self.send_delete.send(DeleteMessage {
+                sequence: change.sequence,
+                event: change.event,
+                transaction,
+                row: change.row,
+            });
+        }
+    }
+}
+
+impl<
+    C: spacetime_codegen::DbConnection<Module = M> + spacetimedb_sdk::DbContext,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+> StdbPlugin<C, M>
+{
+    /// Registers a table without primary key like [`StdbPlugin::add_table_without_pk`], using `key` to tell rows apart.
+    ///
+    /// When a server update deletes a row and inserts one with the same key, they are sent as an
+    /// [`UpdateMessage`] and an [`InsertUpdateMessage`] instead of a [`DeleteMessage`] and an [`InsertMessage`].
+    /// The messages of the table are held back until their update is complete, like with transaction batching.
+    ///
+    /// ```no-run
+    /// plugin.add_table_without_pk_with_key::<PositionsTable, _>(|position| position.entity_id)
+    /// ```
+    pub fn add_table_without_pk_with_key<T, K>(
+        self,
+        key: impl Fn(&T::Row) -> K + Send + Sync + 'static,
+    ) -> Self
+    where
+        T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static,
+        K: Eq + Hash + Send + Sync + 'static,
+    {
+        self.register_channel::<InsertMessage<T::Message>>();
+        self.register_channel::<DeleteMessage<T::Message>>();
+        self.register_channel::<UpdateMessage<T::Message>>();
+        self.register_channel::<InsertUpdateMessage<T::Message>>();
+
+        // A closure that sets up messages for the table
+        let key: RowKeyFn<T::Message, K> = Arc::new(key);
+        let transactions = Arc::clone(&self.transactions);
+        let register = move |senders: &MessageSenders, db: &C::DbView| {
+            let pairs = Arc::new(RowPairs::<T::Message, K> {
+                key: Arc::clone(&key),
+                changes: Mutex::new(Vec::new()),
+                send_insert: message_sender(senders),
+                send_delete: message_sender(senders),
+                send_update: message_sender(senders),
+                send_insert_update: message_sender(senders),
+            });
+            Self::on_change_with_key::<T, K>(&pairs, &transactions, db);
+        };
+
+        // Store this table, and call it on every new connection.
+        self.table_registers
+            .lock()
+            .unwrap()
+            .push(Box::new(register));
+
+        self.system_registers.lock().unwrap().push(Box::new(|app: &mut App| {
+            register_table_changes::<T::Message>(app, true, true, true);
+        }));
+
+        self
+    }
+
+    /// Buffers the rows inserted and deleted in the provided table, sent by [`RowPairs::flush`]
+    /// once their transaction is complete.
+    fn on_change_with_key<T, K>(
+        pairs: &Arc<RowPairs<T::Message, K>>,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) where
+        T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static,
+        K: Eq + Hash + Send + Sync + 'static,
+    {
+        let on_change = |kind: RowChangeKind| {
+            let (pairs, transactions) = (Arc::clone(pairs), Arc::clone(transactions));
+            move |ctx: &T::EventContext, row: &T::Row| {
+                let (pairs, row) = (Arc::clone(&pairs), row.clone());
+                transactions.send_on_complete(T::context_event_accessor(ctx), |event, transaction| {
+                    pairs.changes.lock().unwrap().push(RowChange {
+                        sequence: StdbSequence::next(),
+                        event,
+                        transaction,
+                        kind,
+                        row,
+                    });
+                    Box::new(move || pairs.flush(transaction))
+                });
+            }
+        };
+
+        T::table_accessor(db).on_insert(on_change(RowChangeKind::Insert));
+        T::table_accessor(db).on_delete(on_change(RowChangeKind::Delete));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{Receiver, channel};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Position {
+        entity: u32,
+        x: i32,
+    }
+
+    struct PositionsTable;
+
+    impl TableMessage for PositionsTable {
+        type Row = Position;
+        type Reducer = ();
+    }
+
+    struct Received {
+        inserts: Receiver<InsertMessage<PositionsTable>>,
+        deletes: Receiver<DeleteMessage<PositionsTable>>,
+        updates: Receiver<UpdateMessage<PositionsTable>>,
+        insert_updates: Receiver<InsertUpdateMessage<PositionsTable>>,
+    }
+
+    fn row_pairs() -> (RowPairs<PositionsTable, u32>, Received) {
+        let (send_insert, inserts) = channel();
+        let (send_delete, deletes) = channel();
+        let (send_update, updates) = channel();
+        let (send_insert_update, insert_updates) = channel();
+        let pairs = RowPairs {
+            key: Arc::new(|position: &Position| position.entity),
+            changes: Mutex::new(Vec::new()),
+            send_insert,
+            send_delete,
+            send_update,
+            send_insert_update,
+        };
+        let received = Received {
+            inserts,
+            deletes,
+            updates,
+            insert_updates,
+        };
+        (pairs, received)
+    }
+
+    fn push(
+        pairs: &RowPairs<PositionsTable, u32>,
+        transaction: StdbTransactionId,
+        kind: RowChangeKind,
+        entity: u32,
+        x: i32,
+    ) {
+        pairs.changes.lock().unwrap().push(RowChange {
+            sequence: StdbSequence::next(),
+            event: Event::UnknownTransaction,
+            transaction,
+            kind,
+            row: Position { entity, x },
+        });
+    }
+
+    fn rows<M>(receiver: &Receiver<M>, row: impl Fn(M) -> Position) -> Vec<Position> {
+        receiver.try_iter().map(row).collect()
+    }
+
+    #[test]
+    fn delete_and_insert_with_the_same_key_become_an_update() {
+        let (pairs, received) = row_pairs();
+        let transaction = StdbTransactionId(0);
+        push(&pairs, transaction, RowChangeKind::Delete, 1, 10);
+        push(&pairs, transaction, RowChangeKind::Insert, 1, 20);
+
+        pairs.flush(transaction);
+
+        let updates: Vec<_> = received.updates.try_iter().collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].old, Position { entity: 1, x: 10 });
+        assert_eq!(updates[0].new, Position { entity: 1, x: 20 });
+        assert_eq!(updates[0].transaction, transaction);
+
+        let insert_updates: Vec<_> = received.insert_updates.try_iter().collect();
+        assert_eq!(insert_updates.len(), 1);
+        assert_eq!(insert_updates[0].old, Some(Position { entity: 1, x: 10 }));
+        assert_eq!(insert_updates[0].new, Position { entity: 1, x: 20 });
+
+        assert!(received.inserts.try_recv().is_err());
+        assert!(received.deletes.try_recv().is_err());
+    }
+
+    #[test]
+    fn unpaired_rows_stay_inserts_and_deletes() {
+        let (pairs, received) = row_pairs();
+        let transaction = StdbTransactionId(0);
+        push(&pairs, transaction, RowChangeKind::Delete, 1, 10);
+        push(&pairs, transaction, RowChangeKind::Insert, 2, 20);
+
+        pairs.flush(transaction);
+
+        assert_eq!(rows(&received.inserts, |m| m.row), vec![Position { entity: 2, x: 20 }]);
+        assert_eq!(rows(&received.deletes, |m| m.row), vec![Position { entity: 1, x: 10 }]);
+        let insert_updates: Vec<_> = received.insert_updates.try_iter().collect();
+        assert_eq!(insert_updates.len(), 1);
+        assert_eq!(insert_updates[0].old, None);
+        assert!(received.updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn an_insert_replaces_the_first_deleted_row_with_its_key() {
+        let (pairs, received) = row_pairs();
+        let transaction = StdbTransactionId(0);
+        push(&pairs, transaction, RowChangeKind::Delete, 1, 10);
+        push(&pairs, transaction, RowChangeKind::Delete, 1, 11);
+        push(&pairs, transaction, RowChangeKind::Delete, 2, 20);
+        push(&pairs, transaction, RowChangeKind::Insert, 1, 12);
+
+        pairs.flush(transaction);
+
+        assert_eq!(rows(&received.updates, |m| m.old), vec![Position { entity: 1, x: 10 }]);
+        // The remaining deletions keep the order they were received in
+        assert_eq!(
+            rows(&received.deletes, |m| m.row),
+            vec![Position { entity: 1, x: 11 }, Position { entity: 2, x: 20 }]
+        );
+    }
+
+    #[test]
+    fn flush_only_sends_the_given_transaction() {
+        let (pairs, received) = row_pairs();
+        let (first, second) = (StdbTransactionId(0), StdbTransactionId(1));
+        push(&pairs, first, RowChangeKind::Insert, 1, 10);
+        push(&pairs, second, RowChangeKind::Insert, 2, 20);
+
+        pairs.flush(first);
+        assert_eq!(rows(&received.inserts, |m| m.row), vec![Position { entity: 1, x: 10 }]);
+
+        // Every row of a transaction flushes it, the later calls find nothing to send
+        pairs.flush(first);
+        assert!(received.inserts.try_recv().is_err());
+
+        pairs.flush(second);
+        assert_eq!(rows(&received.inserts, |m| m.row), vec![Position { entity: 2, x: 20 }]);
+        assert!(pairs.changes.lock().unwrap().is_empty());
+    }
+}
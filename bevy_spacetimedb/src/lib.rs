@@ -3,24 +3,49 @@
 //! A bevy plugin for SpacetimeDB.
 
 mod aliases;
+mod cache;
 mod channel_receiver;
+mod commands;
+mod entities;
+mod filtered_tables;
+mod keyed_tables;
 mod messages;
 mod plugin;
 mod procedures;
+mod reconnect;
+mod reducer_calls;
 mod reducers;
+mod run_mode;
+#[cfg(feature = "states")]
+mod state;
 mod stdb_connection;
+mod subscriptions;
 mod tables;
+mod transactions;
 
 pub use aliases::*;
+pub use cache::StdbTable;
 #[cfg(feature = "macros")]
 pub use bevy_spacetimedb_macros::*;
 
-pub use channel_receiver::AddMessageChannelAppExtensions;
+pub use channel_receiver::{AddMessageChannelAppExtensions, MessageChannelSystems};
+pub use commands::StdbCommandsExt;
+pub use entities::{StdbEntityMap, StdbRow, StdbRowToComponents, TablePrimaryKey};
 pub use messages::*;
 pub use plugin::{StdbPlugin, StdbPluginConfig, connect_with_token};
+pub use procedures::RegisterableProcedureMessage;
+pub use reconnect::ReconnectPolicy;
+pub use reducer_calls::{CallableReducer, ReducerCallId, ReducerCallOutcome, ReducerCallPolicy};
 pub use reducers::RegisterableReducerMessage;
+pub use run_mode::{AsyncRunFn, StdbRunMode, StdbRunTask};
+#[cfg(feature = "states")]
+pub use state::StdbConnectionState;
 pub use stdb_connection::*;
+#[cfg(feature = "states")]
+pub use subscriptions::StdbStateSubscriptions;
+pub use subscriptions::{StdbSubscriptionId, StdbSubscriptionState, StdbSubscriptions};
 pub use tables::{
-    RegisterableTable, RegisterableTableWithoutPk, TableMessage, TableMessages,
-    TableMessagesWithoutPrimaryKey,
+    RegisterableTable, RegisterableTableWithoutPk, StdbTableChanged, TableMessage, TableMessages,
+    TableMessagesWithoutPrimaryKey, stdb_table_changed,
 };
+pub use transactions::StdbTransactionId;
@@ -1,34 +1,122 @@
+use std::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
 use bevy::prelude::Message;
 use spacetimedb_sdk::{Error, Event, Identity};
-use crate::tables::TableMessage;
+use crate::{
+    ReducerCallId, ReducerCallOutcome, StdbSubscriptionId, StdbTransactionId, tables::TableMessage,
+};
+
+/// The position of a message in the order the plugin received the events from SpacetimeDB,
+/// shared by every message it emits (table, reducer, connection and subscription messages).
+///
+/// Each message type is forwarded by its own system, so use it to replay messages of different types
+/// in the order the server produced them, e.g. the deletion of a `Player` before the insertion of its `Corpse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StdbSequence(u64);
+
+impl StdbSequence {
+    /// Returns the next sequence number, called when the SDK invokes the callback emitting the message.
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// Connection lifecycle messages are keyed by the connection type `C` (e.g. `DbConnection`),
+// so that several `StdbPlugin`s connected to different modules can coexist in the same app.
 
 /// A message that is emitted when a connection to SpacetimeDB is established.
 #[derive(Message)]
-pub struct StdbConnectedMessage {
+pub struct StdbConnectedMessage<C: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     /// The `Identity`` of the successful connection.
     pub identity: Identity,
     /// The private access token which can be used to later re-authenticate as the same `Identity`.
     pub access_token: String,
+    /// The generation of the connection that was established, see `StdbConnection::generation`.
+    pub(crate) generation: u64,
+    pub(crate) _phantom: PhantomData<C>,
 }
 
 /// A message that is emitted when a connection to SpacetimeDB is lost.
 #[derive(Message)]
-pub struct StdbDisconnectedMessage {
+pub struct StdbDisconnectedMessage<C: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     /// The error that caused the disconnection, if any.
     pub err: Option<Error>,
+    pub(crate) _phantom: PhantomData<C>,
 }
 
-/// A message that is emitted when a connection to SpacetimeDB encounters an error.
+/// A message that is emitted when a connection to SpacetimeDB encounters an error,
+/// including when the connection could not be built at all (e.g. an invalid URI or an unreachable host).
 #[derive(Message)]
-pub struct StdbConnectionErrorMessage {
+pub struct StdbConnectionErrorMessage<C: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     /// The error that occurred.
     pub err: Error,
+    pub(crate) _phantom: PhantomData<C>,
+}
+
+/// A message that is emitted when a subscription has been applied.
+///
+/// `S` is the connection type, or the marker type given to
+/// [`StdbPlugin::add_typed_subscription`](crate::StdbPlugin::add_typed_subscription).
+#[derive(Message)]
+pub struct SubscriptionAppliedMessage<S: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the subscription.
+    pub id: StdbSubscriptionId,
+    /// The queries of the subscription.
+    pub queries: Vec<String>,
+    pub(crate) _phantom: PhantomData<S>,
+}
+
+/// A message that is emitted when a subscription failed or was rejected by the server.
+///
+/// `S` is the connection type, or the marker type given to
+/// [`StdbPlugin::add_typed_subscription`](crate::StdbPlugin::add_typed_subscription).
+#[derive(Message)]
+pub struct SubscriptionErrorMessage<S: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the subscription.
+    pub id: StdbSubscriptionId,
+    /// The queries of the subscription.
+    pub queries: Vec<String>,
+    /// The error that occurred.
+    pub err: Error,
+    pub(crate) _phantom: PhantomData<S>,
+}
+
+/// A message that is emitted when a subscription made with
+/// [`StdbCommandsExt::stdb_subscribe`](crate::StdbCommandsExt::stdb_subscribe) has ended,
+/// either unsubscribed or because its connection was closed.
+#[derive(Message)]
+pub struct SubscriptionEndedMessage<C: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the subscription.
+    pub id: StdbSubscriptionId,
+    /// The queries of the subscription.
+    pub queries: Vec<String>,
+    pub(crate) _phantom: PhantomData<C>,
 }
 
 /// A message that is emitted when a row is inserted into a table.
 #[derive(Message)]
 pub struct InsertMessage<T> where T : TableMessage {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     pub event: Event<T::Reducer>,
+    /// The server update this change belongs to, shared by every row it changed.
+    pub transaction: StdbTransactionId,
     /// The row that was inserted.
     pub row: T::Row,
 }
@@ -36,7 +124,11 @@ pub struct InsertMessage<T> where T : TableMessage {
 /// A message that is emitted when a row is deleted from a table.
 #[derive(Message)]
 pub struct DeleteMessage<T> where T : TableMessage {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     pub event: Event<T::Reducer>,
+    /// The server update this change belongs to, shared by every row it changed.
+    pub transaction: StdbTransactionId,
     /// The row that was deleted.
     pub row: T::Row,
 }
@@ -44,7 +136,11 @@ pub struct DeleteMessage<T> where T : TableMessage {
 /// A message that is emitted when a row is updated in a table.
 #[derive(Message)]
 pub struct UpdateMessage<T> where T : TableMessage {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     pub event: Event<T::Reducer>,
+    /// The server update this change belongs to, shared by every row it changed.
+    pub transaction: StdbTransactionId,
     /// The old row.
     pub old: T::Row,
     /// The new row.
@@ -54,16 +150,36 @@ pub struct UpdateMessage<T> where T : TableMessage {
 /// A message that is emitted when a row is inserted or updated in a table.
 #[derive(Message)]
 pub struct InsertUpdateMessage<T> where T : TableMessage {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     pub event: Event<T::Reducer>,
+    /// The server update this change belongs to, shared by every row it changed.
+    pub transaction: StdbTransactionId,
     /// The previous value of the row if it was updated.
     pub old: Option<T::Row>,
     /// The new value of the row or the inserted value.
     pub new: T::Row,
 }
 
+/// A message that is emitted once every row message of a server update has been emitted,
+/// carrying the same [`StdbTransactionId`] as them.
+///
+/// With [`StdbPlugin::with_transaction_batching`](crate::StdbPlugin::with_transaction_batching),
+/// it is emitted in the same frame as the row messages.
+#[derive(Message)]
+pub struct StdbTransactionMessage<C: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the server update.
+    pub transaction: StdbTransactionId,
+    pub(crate) _phantom: PhantomData<C>,
+}
+
 /// A message that is emitted when a reducer is invoked.
 #[derive(Message, Debug)]
 pub struct ReducerResultMessage<T> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
     /// The result of the reducer invocation.
     pub result: T,
 }
@@ -71,19 +187,90 @@ pub struct ReducerResultMessage<T> {
 impl<T> ReducerResultMessage<T> {
     /// Creates a new reducer result message.
     pub fn new(result: T) -> Self {
-        Self { result }
+        Self {
+            sequence: StdbSequence::next(),
+            result,
+        }
     }
 }
 
+/// A message that is emitted when a procedure returns.
 #[derive(Message, Debug)]
 pub struct ProcedureResultMessage<T> {
-    /// The result of the reducer invocation.
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The result of the procedure invocation.
     pub result: T,
 }
 
 impl<T> ProcedureResultMessage<T> {
-    /// Creates a new reducer result message.
+    /// Creates a new procedure result message.
     pub fn new(result: T) -> Self {
-        Self { result }
+        Self {
+            sequence: StdbSequence::next(),
+            result,
+        }
+    }
+}
+
+/// A message calling the reducer `R`, written by your systems and drained by the plugin,
+/// see [`StdbPlugin::add_reducer_call`](crate::StdbPlugin::add_reducer_call).
+///
+/// Keep its `id` before writing it to match the call with its [`ReducerCallResultMessage`].
+#[derive(Message)]
+pub struct CallReducer<R: Send + Sync + 'static> {
+    /// Identifies this call in the messages reporting its outcome.
+    pub id: ReducerCallId,
+    /// The arguments of the reducer call.
+    pub reducer: R,
+}
+
+impl<R: Send + Sync + 'static> CallReducer<R> {
+    /// Creates a new reducer call message, with a new [`ReducerCallId`].
+    pub fn new(reducer: R) -> Self {
+        Self {
+            id: ReducerCallId::next(),
+            reducer,
+        }
     }
 }
+
+/// A message that is emitted when a [`CallReducer<R>`] could not be sent to SpacetimeDB.
+///
+/// Reducers failing on the server are reported by their [`ReducerCallResultMessage`] instead.
+#[derive(Message)]
+pub struct ReducerCallFailedMessage<R: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the failed call.
+    pub id: ReducerCallId,
+    /// The arguments of the failed reducer call.
+    pub reducer: R,
+    /// Why the call failed, e.g. `Error::Disconnected` when not connected.
+    pub err: Error,
+}
+
+/// A message that is emitted when the server ran a reducer called with a [`CallReducer<R>`] message.
+#[derive(Message)]
+pub struct ReducerCallResultMessage<R: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the call, from its [`CallReducer<R>`] message.
+    pub id: ReducerCallId,
+    /// Whether the reducer committed.
+    pub outcome: ReducerCallOutcome,
+    pub(crate) _phantom: PhantomData<R>,
+}
+
+/// A message that is emitted when no result arrived for a [`CallReducer<R>`] within the timeout set with
+/// [`StdbPlugin::with_reducer_call_timeout`](crate::StdbPlugin::with_reducer_call_timeout).
+///
+/// The [`ReducerCallResultMessage`] of the call may still be emitted later.
+#[derive(Message)]
+pub struct ReducerCallTimeoutMessage<R: Send + Sync + 'static> {
+    /// The order in which the plugin received this message, see [`StdbSequence`].
+    pub sequence: StdbSequence,
+    /// The id of the call, from its [`CallReducer<R>`] message.
+    pub id: ReducerCallId,
+    pub(crate) _phantom: PhantomData<R>,
+}
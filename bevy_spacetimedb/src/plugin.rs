@@ -1,33 +1,73 @@
 use crate::{
-    AddMessageChannelAppExtensions, StdbConnectedMessage, StdbConnection,
-    StdbConnectionErrorMessage, StdbDisconnectedMessage,
+    AddMessageChannelAppExtensions, MessageChannelSystems, ReadStdbConnectedMessage, ReconnectPolicy,
+    StdbConnectedMessage, StdbConnection, StdbConnectionErrorMessage, StdbDisconnectedMessage,
+    StdbSequence, StdbSubscriptions, StdbTransactionMessage, SubscriptionAppliedMessage,
+    SubscriptionEndedMessage, SubscriptionErrorMessage,
+    reconnect::{ReconnectState, reconnect, schedule_reconnect},
+    reducer_calls::StdbReducerCallTimeout,
+    run_mode::{
+        RunThread, StdbRunMode, StdbRunTask, frame_tick, shutdown_on_exit, tick_until_closed,
+    },
+    stdb_connection::next_generation,
+    subscriptions::update_subscriptions,
+    transactions::{StdbTransactions, Transactions, flush_transactions},
 };
+#[cfg(feature = "states")]
+use crate::{
+    StdbConnectionState,
+    state::{ConnectionStateDriver, set_connection_state, update_connection_state},
+};
+#[cfg(feature = "states")]
+use bevy::{prelude::State, state::app::AppExtStates};
 use bevy::{
-    app::{App, Plugin},
-    platform::collections::HashMap,
-    prelude::Resource,
+    app::{App, Last, Plugin, PreUpdate},
+    log::{error, warn},
+    platform::{collections::HashMap, time::Instant},
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::{IntoScheduleConfigs, Message, ResMut, Resource, World},
 };
 use std::marker::PhantomData;
 use spacetimedb_sdk::{Compression, DbConnectionBuilder, DbContext};
 use std::{
     any::{Any, TypeId},
+    future::Future,
     sync::{Arc, Mutex, mpsc::{channel, Sender}},
     thread::JoinHandle,
+    time::Duration,
 };
 
-/// Configuration for delayed SpacetimeDB connection
+/// Senders for every registered message channel, keyed by the message type.
+pub(crate) type MessageSenders = Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>;
+
+/// Returns the sender of a message channel registered with [`StdbPlugin::register_channel`].
+pub(crate) fn message_sender<E: Message>(senders: &MessageSenders) -> Sender<E> {
+    senders
+        .lock()
+        .unwrap()
+        .get(&TypeId::of::<E>())
+        .expect("Message channel not registered")
+        .downcast_ref::<Sender<E>>()
+        .expect("Sender type mismatch")
+        .clone()
+}
+
+/// Configuration used to build (and rebuild) the SpacetimeDB connection
 pub struct StdbPluginConfig<
     C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
     M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
 > {
     pub module_name: String,
     pub uri: String,
-    pub run_fn: fn(&C) -> JoinHandle<()>,
+    /// The token used when the connection is rebuilt, updated with the access token of every successful connection.
+    pub token: Option<String>,
+    pub run_mode: StdbRunMode<C>,
     pub compression: Compression,
     pub light_mode: bool,
-    pub send_connected: Sender<StdbConnectedMessage>,
-    pub send_disconnected: Sender<StdbDisconnectedMessage>,
-    pub send_connect_error: Sender<StdbConnectionErrorMessage>,
+    /// How long to wait for the connection to close when the app exits, or when a frame-ticked connection is closed.
+    pub shutdown_timeout: Duration,
+    pub send_connected: Sender<StdbConnectedMessage<C>>,
+    pub send_disconnected: Sender<StdbDisconnectedMessage<C>>,
+    pub send_connect_error: Sender<StdbConnectionErrorMessage<C>>,
     _phantom: PhantomData<(C, M)>,
 }
 
@@ -37,102 +77,248 @@ impl<
     M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C> + 'static,
 > Resource for StdbPluginConfig<C, M> {}
 
-/// Stores plugin data (table/reducer registrations) for delayed connection
-struct DelayedPluginData<
+/// Stores plugin data (table/reducer/procedure/subscription registrations) so they can be applied to every new connection
+struct StdbRegistrations<
     C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
     M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
 > {
-    message_senders: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+    message_senders: MessageSenders,
     #[allow(clippy::type_complexity)]
     table_registers: Arc<Mutex<Vec<
-        Box<dyn Fn(&StdbPlugin<C, M>, &mut App, &'static <C as DbContext>::DbView) + Send + Sync>,
+        Box<dyn Fn(&MessageSenders, &<C as DbContext>::DbView) + Send + Sync>,
     >>>,
     #[allow(clippy::type_complexity)]
-    reducer_registers: Arc<Mutex<Vec<Box<dyn Fn(&mut App, &<C as DbContext>::Reducers) + Send + Sync>>>>,
+    reducer_registers: Arc<Mutex<Vec<Box<dyn Fn(&MessageSenders, &<C as DbContext>::Reducers) + Send + Sync>>>>,
+    #[allow(clippy::type_complexity)]
+    procedure_registers: Arc<Mutex<Vec<Box<dyn Fn(&MessageSenders, &<C as DbContext>::Procedures) + Send + Sync>>>>,
+    #[allow(clippy::type_complexity)]
+    subscription_registers: Arc<Mutex<Vec<Box<dyn Fn(&MessageSenders, &C) + Send + Sync>>>>,
+    _phantom: PhantomData<M>,
 }
 
-/// Connect to SpacetimeDB with the given token (for delayed connection mode)
-/// 
-/// Call this from an exclusive system (system with `world: &mut World` parameter)
-/// after OAuth completes to establish the connection with the token.
-pub fn connect_with_token<
+impl<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync + 'static,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C> + 'static,
+> Resource for StdbRegistrations<C, M> {}
+
+/// Builds a new connection from the stored [`StdbPluginConfig`], registers every table, reducer and procedure
+/// against it, starts it with the run function and inserts it as the [`StdbConnection`] resource.
+pub(crate) fn connect<
     C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
     M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
 >(
-    world: &mut bevy::prelude::World,
+    world: &mut World,
     token: Option<String>,
-) {
-    let config = world.remove_resource::<StdbPluginConfig<C, M>>()
-        .expect("StdbPluginConfig not found - did you call with_delayed_connect()?");
-    
-    let plugin_data = world.remove_non_send_resource::<DelayedPluginData<C, M>>()
-        .expect("DelayedPluginData not found");
-    
+) -> spacetimedb_sdk::Result<()> {
+    close_connection::<C, M>(world);
+    if let Some(mut reconnect) = world.get_resource_mut::<ReconnectState<C, M>>() {
+        reconnect.resume();
+    }
+
+    #[cfg(feature = "states")]
+    set_connection_state::<C>(world, StdbConnectionState::Connecting);
+
+    let config = world
+        .get_resource::<StdbPluginConfig<C, M>>()
+        .expect("StdbPluginConfig not found - is the StdbPlugin added?");
+    let registrations = world
+        .get_resource::<StdbRegistrations<C, M>>()
+        .expect("StdbRegistrations not found - is the StdbPlugin added?");
+
+    let subscription_registers = Arc::clone(&registrations.subscription_registers);
+    let subscription_senders = Arc::clone(&registrations.message_senders);
     let send_connected = config.send_connected.clone();
     let send_disconnected = config.send_disconnected.clone();
     let send_connect_error = config.send_connect_error.clone();
-    
+    // The connected message tells which connection it belongs to, as the previous one may still be around
+    let generation = next_generation();
+
     let conn = DbConnectionBuilder::<M>::new()
-        .with_module_name(config.module_name)
-        .with_uri(config.uri)
+        .with_module_name(config.module_name.clone())
+        .with_uri(config.uri.clone())
         .with_token(token)
         .with_compression(config.compression)
         .with_light_mode(config.light_mode)
         .on_connect_error(move |_ctx, err| {
-            send_connect_error
-                .send(StdbConnectionErrorMessage { err })
-                .unwrap();
+            let _ = send_connect_error.send(StdbConnectionErrorMessage {
+                sequence: StdbSequence::next(),
+                err,
+                _phantom: PhantomData,
+            });
         })
         .on_disconnect(move |_ctx, err| {
-            send_disconnected
-                .send(StdbDisconnectedMessage { err })
-                .unwrap();
+            let _ = send_disconnected.send(StdbDisconnectedMessage {
+                sequence: StdbSequence::next(),
+                err,
+                _phantom: PhantomData,
+            });
         })
-        .on_connect(move |_ctx, id, token| {
-            send_connected
-                .send(StdbConnectedMessage {
-                    identity: id,
-                    access_token: token.to_string(),
-                })
-                .unwrap();
+        .on_connect(move |ctx, id, token| {
+            // Subscribe before anything else, so the subscriptions are applied on every (re)connection
+            for subscription_register in subscription_registers.lock().unwrap().iter() {
+                subscription_register(&subscription_senders, ctx);
+            }
+
+            let _ = send_connected.send(StdbConnectedMessage {
+                sequence: StdbSequence::next(),
+                identity: id,
+                access_token: token.to_string(),
+                generation,
+                _phantom: PhantomData,
+            });
         })
-        .build()
-        .expect("Failed to build delayed connection");
-
-    let conn = Box::<C>::leak(Box::new(conn));
-
-    // NOW register tables and reducers with the actual connection!
-    // Create a temporary plugin with the stored message senders
-    let temp_plugin = StdbPlugin::<C, M> {
-        module_name: None,
-        uri: None,
-        token: None,
-        run_fn: None,
-        compression: None,
-        light_mode: false,
-        delayed_connect: false,
-        message_senders: Arc::clone(&plugin_data.message_senders),
-        table_registers: Arc::new(Mutex::new(Vec::new())),
-        reducer_registers: Arc::new(Mutex::new(Vec::new())),
-        procedure_registers: Arc::new(Mutex::new(Vec::new())),
+        .build()?;
+
+    let conn = Arc::new(conn);
+
+    {
+        let table_regs = registrations.table_registers.lock().unwrap();
+        for table_register in table_regs.iter() {
+            table_register(&registrations.message_senders, conn.db());
+        }
+    }
+    {
+        let reducer_regs = registrations.reducer_registers.lock().unwrap();
+        for reducer_register in reducer_regs.iter() {
+            reducer_register(&registrations.message_senders, conn.reducers());
+        }
+    }
+    {
+        let procedure_regs = registrations.procedure_registers.lock().unwrap();
+        for procedure_register in procedure_regs.iter() {
+            procedure_register(&registrations.message_senders, conn.procedures());
+        }
+    }
+
+    let run_mode = config.run_mode.clone();
+    run_mode.start(world, &conn);
+    world.insert_resource(StdbConnection::with_generation(conn, generation));
+
+    Ok(())
+}
+
+/// Removes the [`StdbConnection`] resource, disconnecting it if it is still active.
+///
+/// The client cache is freed once the run function has processed the disconnection.
+/// In [`StdbRunMode::FrameTick`], the connection is ticked until then, up to the configured shutdown timeout.
+pub(crate) fn close_connection<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    world: &mut World,
+) {
+    // Subscriptions don't carry over to the next connection
+    if let Some(mut subscriptions) = world.get_resource_mut::<StdbSubscriptions<C>>() {
+        subscriptions.end_all();
+    }
+
+    // The thread or task of the previous connection finishes processing the disconnection on its own
+    world.remove_resource::<RunThread<C>>();
+    if let Some(task) = world.remove_resource::<StdbRunTask<C>>() {
+        task.detach();
+    }
+
+    let Some(conn) = world.remove_resource::<StdbConnection<C>>() else {
+        return;
     };
-    
-    // Register tables with the real connection
-    let table_regs = plugin_data.table_registers.lock().unwrap();
-    for table_register in table_regs.iter() {
-        table_register(&temp_plugin, unsafe { &mut *(world as *mut _ as *mut App) }, conn.db());
+
+    if !conn.is_active() {
+        return;
     }
-    drop(table_regs);
-    
-    // Register reducers
-    let reducer_regs = plugin_data.reducer_registers.lock().unwrap();
-    for reducer_register in reducer_regs.iter() {
-        reducer_register(unsafe { &mut *(world as *mut _ as *mut App) }, conn.reducers());
+    if let Err(err) = conn.disconnect() {
+        warn!("Failed to disconnect from SpacetimeDB: {}", err);
+        return;
     }
-    drop(reducer_regs);
 
-    (config.run_fn)(conn);
-    world.insert_resource(StdbConnection::new(conn));
+    // A frame-ticked connection only closes when ticked, which emits its StdbDisconnectedMessage
+    let config = world.resource::<StdbPluginConfig<C, M>>();
+    if let StdbRunMode::FrameTick(tick_fn) = config.run_mode {
+        if !tick_until_closed(conn.conn(), tick_fn, Instant::now() + config.shutdown_timeout) {
+            warn!(
+                "SpacetimeDB connection did not close within {:?}",
+                config.shutdown_timeout
+            );
+        }
+        // Every update received before the disconnection has been processed
+        world
+            .resource::<StdbTransactions<C>>()
+            .transactions
+            .complete_current();
+    }
+}
+
+/// Closes the connection and stops reconnecting automatically until the next [`connect`].
+pub(crate) fn disconnect<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    world: &mut World,
+) {
+    if let Some(mut reconnect) = world.get_resource_mut::<ReconnectState<C, M>>() {
+        reconnect.pause();
+    }
+    close_connection::<C, M>(world);
+
+    #[cfg(feature = "states")]
+    set_connection_state::<C>(world, StdbConnectionState::Disconnected);
+}
+
+/// Same as [`connect`], but a failed build is reported as a [`StdbConnectionErrorMessage`]
+/// instead of being returned, leaving the app without a [`StdbConnection`] until the next attempt.
+pub(crate) fn try_connect<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    world: &mut World,
+    token: Option<String>,
+) {
+    if let Err(err) = connect::<C, M>(world, token) {
+        error!("Failed to build SpacetimeDB connection: {}", err);
+        let _ = world
+            .resource::<StdbPluginConfig<C, M>>()
+            .send_connect_error
+            .send(StdbConnectionErrorMessage {
+                sequence: StdbSequence::next(),
+                err,
+                _phantom: PhantomData,
+            });
+    }
+}
+
+/// Keeps the access token of the last successful connection in the [`StdbPluginConfig`],
+/// so the connection is rebuilt as the same identity.
+fn store_token<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    mut connected: ReadStdbConnectedMessage<C>,
+    mut config: ResMut<StdbPluginConfig<C, M>>,
+) {
+    if let Some(message) = connected.read().last() {
+        config.token = Some(message.access_token.clone());
+    }
+}
+
+/// Connect to SpacetimeDB with the given token (for delayed connection mode)
+///
+/// Call this from an exclusive system (system with `world: &mut World` parameter)
+/// after OAuth completes to establish the connection with the token.
+///
+/// If the connection can't be built, a [`StdbConnectionErrorMessage`] is emitted
+/// and this function can be called again to retry.
+pub fn connect_with_token<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    world: &mut World,
+    token: Option<String>,
+) {
+    world
+        .get_resource_mut::<StdbPluginConfig<C, M>>()
+        .expect("StdbPluginConfig not found - did you call with_delayed_connect()?")
+        .token = token.clone();
+
+    try_connect::<C, M>(world, token);
 }
 
 /// The plugin for connecting SpacetimeDB with your bevy application.
@@ -143,23 +329,43 @@ pub struct StdbPlugin<
     module_name: Option<String>,
     uri: Option<String>,
     token: Option<String>,
-    run_fn: Option<fn(&C) -> JoinHandle<()>>,
+    run_mode: Option<StdbRunMode<C>>,
+    frame_tick_schedule: InternedScheduleLabel,
     compression: Option<Compression>,
     light_mode: bool,
+    shutdown_timeout: Duration,
     delayed_connect: bool,  // NEW: Skip immediate connection
+    reconnect_policy: Option<ReconnectPolicy>,
+    transaction_batching: bool,
+    reducer_call_timeout: Option<Duration>,
 
+    // Groups the table messages by server update, shared by every table callback.
+    pub(crate) transactions: Arc<Transactions>,
     // Stores Senders for registered table messages.
-    pub(crate) message_senders: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+    pub(crate) message_senders: MessageSenders,
+    // Registers the message channels, called once when the plugin is built.
+    #[allow(clippy::type_complexity)]
+    pub(crate) channel_registers:
+        Arc<Mutex<Vec<Box<dyn Fn(&mut App, &MessageSenders) + Send + Sync>>>>,
+    // Registers the SpacetimeDB callbacks, called on every new connection.
     #[allow(clippy::type_complexity)]
     pub(crate) table_registers: Arc<Mutex<Vec<
-        Box<dyn Fn(&StdbPlugin<C, M>, &mut App, &'static <C as DbContext>::DbView) + Send + Sync>,
+        Box<dyn Fn(&MessageSenders, &<C as DbContext>::DbView) + Send + Sync>,
     >>>,
     #[allow(clippy::type_complexity)]
     pub(crate) reducer_registers:
-        Arc<Mutex<Vec<Box<dyn Fn(&mut App, &<C as DbContext>::Reducers) + Send + Sync>>>>,
+        Arc<Mutex<Vec<Box<dyn Fn(&MessageSenders, &<C as DbContext>::Reducers) + Send + Sync>>>>,
+    // Subscribes to the registered queries, called from the on_connect callback of every new connection.
+    #[allow(clippy::type_complexity)]
+    pub(crate) subscription_registers:
+        Arc<Mutex<Vec<Box<dyn Fn(&MessageSenders, &C) + Send + Sync>>>>,
+    // Adds the systems needed by the registered features (entity mirroring, state subscriptions...),
+    // called once when the plugin is built.
+    #[allow(clippy::type_complexity)]
+    pub(crate) system_registers: Arc<Mutex<Vec<Box<dyn Fn(&mut App) + Send + Sync>>>>,
     #[allow(clippy::type_complexity)]
     pub(crate) procedure_registers:
-        Arc<Mutex<Vec<Box<dyn Fn(&mut App, &<C as DbContext>::Procedures) + Send + Sync>>>>,
+        Arc<Mutex<Vec<Box<dyn Fn(&MessageSenders, &<C as DbContext>::Procedures) + Send + Sync>>>>,
 }
 
 impl<
@@ -172,14 +378,23 @@ impl<
             module_name: Default::default(),
             uri: None,
             token: None,
-            run_fn: None,
+            run_mode: None,
+            frame_tick_schedule: PreUpdate.intern(),
             compression: Some(Compression::default()),
             light_mode: false,
+            shutdown_timeout: Duration::from_secs(1),
             delayed_connect: false,  // NEW: Default to immediate connection
+            reconnect_policy: None,
+            transaction_batching: false,
+            reducer_call_timeout: None,
+            transactions: Arc::new(Transactions::default()),
 
             message_senders: Arc::new(Mutex::default()),
+            channel_registers: Arc::new(Mutex::new(Vec::default())),
             table_registers: Arc::new(Mutex::new(Vec::default())),
             reducer_registers: Arc::new(Mutex::new(Vec::default())),
+            subscription_registers: Arc::new(Mutex::new(Vec::default())),
+            system_registers: Arc::new(Mutex::new(Vec::default())),
             procedure_registers: Arc::new(Mutex::new(Vec::default())),
         }
     }
@@ -194,7 +409,45 @@ impl<
     ///
     /// Other function are not tested, they may not work.
     pub fn with_run_fn(mut self, run_fn: fn(&C) -> JoinHandle<()>) -> Self {
-        self.run_fn = Some(run_fn);
+        self.run_mode = Some(StdbRunMode::Threaded(run_fn));
+        self
+    }
+
+    /// Process the connection's messages from a Bevy system instead of a dedicated thread,
+    /// by calling `tick_fn` once per run of the frame tick schedule. Use `DbConnection::frame_tick`.
+    ///
+    /// Callbacks then run deterministically on the main schedule, and a test can step the network
+    /// exactly once per `app.update()`.
+    pub fn with_frame_tick(mut self, tick_fn: fn(&C) -> spacetimedb_sdk::Result<()>) -> Self {
+        self.run_mode = Some(StdbRunMode::FrameTick(tick_fn));
+        self
+    }
+
+    /// Process the connection's messages in a task on Bevy's `IoTaskPool` instead of a dedicated thread.
+    ///
+    /// `run_async` returns the future driving the connection, usually `DbConnection::run_async`:
+    ///
+    /// ```ignore
+    /// StdbPlugin::default().with_async_runtime(|conn| async move { conn.run_async().await })
+    /// ```
+    ///
+    /// The task is exposed as the [`StdbRunTask`] resource, and is stopped when the app exits.
+    pub fn with_async_runtime<F, Fut>(mut self, run_async: F) -> Self
+    where
+        F: Fn(Arc<C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = spacetimedb_sdk::Result<()>> + Send + 'static,
+    {
+        self.run_mode = Some(StdbRunMode::Async(Arc::new(move |conn| {
+            Box::pin(run_async(conn))
+        })));
+        self
+    }
+
+    /// Set the schedule in which the connection is ticked when using [`StdbPlugin::with_frame_tick`].
+    ///
+    /// Defaults to [`PreUpdate`], right before the messages are forwarded to Bevy.
+    pub fn with_frame_tick_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.frame_tick_schedule = schedule.intern();
         self
     }
 
@@ -250,6 +503,40 @@ impl<
         self
     }
 
+    /// Holds back the table messages of a server update until every row it changed has been received.
+    /// Defaults to `false`.
+    ///
+    /// With [`StdbPlugin::with_frame_tick`], an update is complete once the tick has processed it,
+    /// so a frame never observes half of a transaction.
+    /// Otherwise the connection runs on its own thread or task, and an update is complete once a row from
+    /// another update arrives, or when no row arrived for a whole frame: this delays the last update by a frame,
+    /// and an update still being received after a quiet frame can be split across frames.
+    /// In light mode, consecutive updates made by other clients can't be told apart and are grouped together.
+    pub fn with_transaction_batching(mut self, transaction_batching: bool) -> Self {
+        self.transaction_batching = transaction_batching;
+        self
+    }
+
+    /// Emits a [`ReducerCallTimeoutMessage`](crate::ReducerCallTimeoutMessage) for the reducer calls
+    /// made with a [`CallReducer`](crate::CallReducer) message
+    /// that got no result within `timeout`. Disabled by default.
+    pub fn with_reducer_call_timeout(mut self, timeout: Duration) -> Self {
+        self.reducer_call_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait, when the app exits, for the connection to send its pending messages
+    /// and close. Defaults to one second.
+    ///
+    /// On `AppExit` the plugin disconnects, so the server runs its `client_disconnected` reducer,
+    /// and blocks until the run thread or task has finished or the timeout is reached.
+    /// In [`StdbRunMode::FrameTick`], closing or replacing the connection waits the same way,
+    /// ticking it until it has processed the disconnection.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
     /// Enable delayed connection mode. The connection will not be started
     /// during plugin build. You must manually call `connect_with_token()` later.
     ///
@@ -258,6 +545,32 @@ impl<
         self.delayed_connect = delayed;
         self
     }
+
+    /// Automatically reconnect when the connection is lost or fails, following the given [`ReconnectPolicy`].
+    ///
+    /// The connection is rebuilt with the access token of the last successful connection,
+    /// every registered table, reducer and procedure is registered again and the [`StdbConnection`] resource is replaced.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// Registers a message channel for `E` when the plugin is built, once per message type.
+    pub(crate) fn register_channel<E: Message>(&self) {
+        let register = |app: &mut App, senders: &MessageSenders| {
+            senders
+                .lock()
+                .unwrap()
+                .entry(TypeId::of::<E>())
+                .or_insert_with(|| {
+                    let (send, recv) = channel::<E>();
+                    app.add_message_channel(recv);
+                    Box::new(send)
+                });
+        };
+
+        self.channel_registers.lock().unwrap().push(Box::new(register));
+    }
 }
 
 impl<
@@ -273,88 +586,117 @@ impl<
             "No module name set for StdbPlugin. Set it with the with_module_name() function",
         );
 
-        let (send_connected, recv_connected) = channel::<StdbConnectedMessage>();
-        let (send_disconnected, recv_disconnected) = channel::<StdbDisconnectedMessage>();
-        let (send_connect_error, recv_connect_error) = channel::<StdbConnectionErrorMessage>();
-        app.add_message_channel::<StdbConnectionErrorMessage>(recv_connect_error)
-            .add_message_channel::<StdbConnectedMessage>(recv_connected)
-            .add_message_channel::<StdbDisconnectedMessage>(recv_disconnected);
+        let (send_connected, recv_connected) = channel::<StdbConnectedMessage<C>>();
+        let (send_disconnected, recv_disconnected) = channel::<StdbDisconnectedMessage<C>>();
+        let (send_connect_error, recv_connect_error) = channel::<StdbConnectionErrorMessage<C>>();
+        app.add_message_channel::<StdbConnectionErrorMessage<C>>(recv_connect_error)
+            .add_message_channel::<StdbConnectedMessage<C>>(recv_connected)
+            .add_message_channel::<StdbDisconnectedMessage<C>>(recv_disconnected);
 
-        // NEW: Check if we should delay the connection
-        if self.delayed_connect {
-            // Store configuration AND table/reducer registrations for later connection
-            app.insert_resource(StdbPluginConfig::<C, M> {
-                module_name: self.module_name.clone().unwrap(),
-                uri: self.uri.clone().unwrap(),
-                run_fn: self.run_fn.expect("No run function specified!"),
-                compression: self.compression.unwrap_or_default(),
-                light_mode: self.light_mode,
-                send_connected,
-                send_disconnected,
-                send_connect_error,
+        // Messages of the subscriptions made at runtime through commands
+        self.register_channel::<SubscriptionAppliedMessage<C>>();
+        self.register_channel::<SubscriptionErrorMessage<C>>();
+        self.register_channel::<SubscriptionEndedMessage<C>>();
+
+        {
+            let channel_regs = self.channel_registers.lock().unwrap();
+            for channel_register in channel_regs.iter() {
+                channel_register(app, &self.message_senders);
+            }
+        }
+
+        // Store configuration AND table/reducer/procedure/subscription registrations, they are needed for every (re)connection
+        app.insert_resource(StdbPluginConfig::<C, M> {
+            module_name: self.module_name.clone().unwrap(),
+            uri: self.uri.clone().unwrap(),
+            token: self.token.clone(),
+            run_mode: self
+                .run_mode
+                .clone()
+                .expect("No run function specified! Set it with with_run_fn(), with_frame_tick() or with_async_runtime()"),
+            compression: self.compression.unwrap_or_default(),
+            light_mode: self.light_mode,
+            shutdown_timeout: self.shutdown_timeout,
+            send_connected,
+            send_disconnected,
+            send_connect_error,
+            _phantom: PhantomData,
+        });
+        app.insert_resource(StdbRegistrations::<C, M> {
+            table_registers: Arc::clone(&self.table_registers),
+            reducer_registers: Arc::clone(&self.reducer_registers),
+            procedure_registers: Arc::clone(&self.procedure_registers),
+            subscription_registers: Arc::clone(&self.subscription_registers),
+            message_senders: Arc::clone(&self.message_senders),
+            _phantom: PhantomData,
+        });
+
+        self.transactions.set_batching(self.transaction_batching);
+        if let Some(timeout) = self.reducer_call_timeout {
+            app.insert_resource(StdbReducerCallTimeout::<C> {
+                timeout,
                 _phantom: PhantomData,
             });
-            
-            // Clone the Arc pointers to share the data with connect_with_token
-            let plugin_for_later = DelayedPluginData::<C, M> {
-                table_registers: Arc::clone(&self.table_registers),
-                reducer_registers: Arc::clone(&self.reducer_registers),
-                message_senders: Arc::clone(&self.message_senders),
-            };
-            app.insert_non_send_resource(plugin_for_later);
-            
-            return; // Skip connection - it will be created later via connect_with_token
         }
-
-        // FIXME App should not crash if intial connection fails.
-        let conn = DbConnectionBuilder::<M>::new()
-            .with_module_name(self.module_name.clone().unwrap())
-            .with_uri(self.uri.clone().unwrap())
-            .with_token(self.token.clone())
-            .with_compression(self.compression.unwrap_or_default())
-            .with_light_mode(self.light_mode)
-            .on_connect_error(move |_ctx, err| {
-                send_connect_error
-                    .send(StdbConnectionErrorMessage { err })
-                    .unwrap();
-            })
-            .on_disconnect(move |_ctx, err| {
-                send_disconnected
-                    .send(StdbDisconnectedMessage { err })
-                    .unwrap();
-            })
-            .on_connect(move |_ctx, id, token| {
-                send_connected
-                    .send(StdbConnectedMessage {
-                        identity: id,
-                        access_token: token.to_string(),
-                    })
-                    .unwrap();
+        app.add_message::<StdbTransactionMessage<C>>()
+            .insert_resource(StdbTransactions::<C> {
+                transactions: Arc::clone(&self.transactions),
+                _phantom: PhantomData,
             })
-            .build()
-            .expect("Failed to build connection");
+            .add_systems(
+                PreUpdate,
+                flush_transactions::<C>
+                    .before(MessageChannelSystems)
+                    .after(frame_tick::<C, M>),
+            );
 
-        // A 'static ref is needed for the connection the register tables and reducers
-        // This is fine because only a small and fixed amount of memory will be leaked
-        // Because conn has to live until the end of the program anyways, not using it would not make for any performance improvements.
-        let conn = Box::<C>::leak(Box::new(conn));
+        app.insert_resource(StdbSubscriptions::<C>::new(&self.message_senders))
+            .add_systems(
+                PreUpdate,
+                update_subscriptions::<C>.after(MessageChannelSystems),
+            );
+        for system_register in self.system_registers.lock().unwrap().iter() {
+            system_register(app);
+        }
 
-        {
-            let table_regs = self.table_registers.lock().unwrap();
-            for table_register in table_regs.iter() {
-                table_register(self, app, conn.db());
-            }
+        app.add_systems(PreUpdate, store_token::<C, M>.after(MessageChannelSystems));
+        if let Some(policy) = &self.reconnect_policy {
+            app.insert_resource(ReconnectState::<C, M>::new(policy.clone()))
+                .add_systems(
+                    PreUpdate,
+                    (schedule_reconnect::<C, M>, reconnect::<C, M>)
+                        .chain()
+                        .after(store_token::<C, M>),
+                );
         }
-        {
-            let reducer_regs = self.reducer_registers.lock().unwrap();
-            for reducer_register in reducer_regs.iter() {
-                reducer_register(app, conn.reducers());
-            }
+
+        if let Some(StdbRunMode::FrameTick(_)) = self.run_mode {
+            app.add_systems(
+                self.frame_tick_schedule,
+                frame_tick::<C, M>.before(MessageChannelSystems),
+            );
+        }
+        app.add_systems(Last, shutdown_on_exit::<C, M>);
+
+        // The state follows the first connection when several plugins are added
+        #[cfg(feature = "states")]
+        if !app.world().contains_resource::<State<StdbConnectionState>>() {
+            app.init_state::<StdbConnectionState>()
+                .insert_resource(ConnectionStateDriver::<C>(PhantomData))
+                .add_systems(
+                    PreUpdate,
+                    update_connection_state::<C, M>
+                        .after(MessageChannelSystems)
+                        .after(reconnect::<C, M>),
+                );
         }
 
-        let run_fn = self.run_fn.expect("No run function specified!");
-        run_fn(conn);
+        // NEW: Check if we should delay the connection
+        if self.delayed_connect {
+            return; // Skip connection - it will be created later via connect_with_token
+        }
 
-        app.insert_resource(StdbConnection::new(conn));
+        // A failed initial connection is reported as a StdbConnectionErrorMessage, the app keeps running without a StdbConnection.
+        try_connect::<C, M>(app.world_mut(), self.token.clone());
     }
 }
@@ -1,7 +1,9 @@
-use crate::{AddMessageChannelAppExtensions, ProcedureResultMessage, StdbPlugin};
-use bevy::app::App;
+use crate::{
+    ProcedureResultMessage, StdbPlugin,
+    plugin::{MessageSenders, message_sender},
+};
 use spacetimedb_sdk::__codegen as spacetime_codegen;
-use std::sync::mpsc::{Sender, channel};
+use std::sync::mpsc::Sender;
 
 /// Trait for making a procedure registerable into the bevy application.
 pub trait RegisterableProcedureMessage<
@@ -21,16 +23,16 @@ impl<
 {
     /// Registers a procedure message <E> for the bevy application.
     pub fn add_procedure<E: RegisterableProcedureMessage<C, M> + Send + Sync + 'static>(
-        mut self,
+        self,
     ) -> Self {
+        self.register_channel::<ProcedureResultMessage<E>>();
+
         // This callback manages the registration of the message.
-        let register_fn = move |app: &mut App, procedures: &C::Procedures| {
-            let (send, recv) = channel::<ProcedureResultMessage<E>>();
-            app.add_message_channel(recv);
-            E::set_stdb_callback(procedures, send);
+        let register_fn = move |senders: &MessageSenders, procedures: &C::Procedures| {
+            E::set_stdb_callback(procedures, message_sender::<ProcedureResultMessage<E>>(senders));
         };
 
-        // The register_fn will get called once the connection is built.
+        // The register_fn will get called every time a connection is built.
         self.procedure_registers.lock().unwrap().push(Box::new(register_fn));
 
         self
@@ -0,0 +1,255 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    marker::PhantomData,
+    time::Duration,
+};
+
+use bevy::{
+    log::{info, warn},
+    platform::time::Instant,
+    prelude::{Commands, Res, ResMut, Resource, World},
+};
+use spacetimedb_sdk::DbContext;
+
+use crate::{
+    ReadStdbConnectedMessage, ReadStdbConnectionErrorMessage, ReadStdbDisconnectedMessage,
+    StdbConnection, StdbPluginConfig, plugin::try_connect,
+};
+
+/// Passed into [`StdbPlugin::with_reconnect_policy`](crate::StdbPlugin::with_reconnect_policy)
+/// to control how a lost connection is re-established.
+///
+/// The delay before attempt `n` is `initial_delay * multiplier^(n - 1)`, capped at `max_delay`,
+/// of which a random fraction (`jitter`) is subtracted so clients don't all reconnect at once.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// The delay before the first reconnection attempt.
+    pub initial_delay: Duration,
+    /// The maximum delay between two reconnection attempts.
+    pub max_delay: Duration,
+    /// The factor by which the delay grows after each failed attempt.
+    pub multiplier: f32,
+    /// The fraction of the delay that is randomized, between `0.0` and `1.0`.
+    pub jitter: f32,
+    /// The number of consecutive attempts before giving up, `None` to retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Set the delay before the first reconnection attempt.
+    pub fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Set the maximum delay between two reconnection attempts.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the factor by which the delay grows after each failed attempt.
+    pub fn with_multiplier(mut self, multiplier: f32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the fraction of the delay that is randomized, clamped between `0.0` and `1.0`.
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Give up after `max_attempts` consecutive failed attempts.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Returns the delay to wait before the given attempt, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial_delay.as_secs_f32() * self.multiplier.max(1.0).powi(exponent))
+            .min(self.max_delay.as_secs_f32());
+
+        // RandomState is randomly seeded, which is enough to spread reconnecting clients apart.
+        let random = RandomState::new().hash_one(attempt) as f32 / u64::MAX as f32;
+        let jitter = delay * self.jitter.clamp(0.0, 1.0) * random;
+
+        Duration::from_secs_f32((delay - jitter).max(0.0))
+    }
+}
+
+/// Tracks the pending reconnection attempt of a connection.
+pub(crate) struct ReconnectState<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+> {
+    policy: ReconnectPolicy,
+    attempt: u32,
+    next_attempt: Option<Instant>,
+    paused: bool,
+    _phantom: PhantomData<(C, M)>,
+}
+
+impl<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync + 'static,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C> + 'static,
+> Resource for ReconnectState<C, M> {}
+
+impl<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+> ReconnectState<C, M>
+{
+    pub(crate) fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            attempt: 0,
+            next_attempt: None,
+            paused: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Stops reconnecting, used when the connection is closed on purpose.
+    pub(crate) fn pause(&mut self) {
+        self.paused = true;
+        self.next_attempt = None;
+    }
+
+    /// Reconnects again when the connection is lost, cancelling any scheduled attempt.
+    pub(crate) fn resume(&mut self) {
+        self.paused = false;
+        self.next_attempt = None;
+    }
+
+    /// Returns `true` if a reconnection attempt is scheduled.
+    #[cfg(feature = "states")]
+    pub(crate) fn is_pending(&self) -> bool {
+        self.next_attempt.is_some()
+    }
+}
+
+/// Schedules a reconnection attempt whenever the connection is lost or fails to connect.
+pub(crate) fn schedule_reconnect<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    mut connected: ReadStdbConnectedMessage<C>,
+    mut disconnected: ReadStdbDisconnectedMessage<C>,
+    mut errors: ReadStdbConnectionErrorMessage<C>,
+    mut state: ResMut<ReconnectState<C, M>>,
+    conn: Option<Res<StdbConnection<C>>>,
+) {
+    if connected.read().count() > 0 {
+        state.attempt = 0;
+        state.next_attempt = None;
+    }
+
+    // Messages from a replaced connection are ignored while the current one is active
+    let lost = disconnected.read().count() + errors.read().count() > 0
+        && !conn.is_some_and(|conn| conn.is_active());
+    if !lost || state.paused || state.next_attempt.is_some() {
+        return;
+    }
+
+    if state
+        .policy
+        .max_attempts
+        .is_some_and(|max_attempts| state.attempt >= max_attempts)
+    {
+        warn!(
+            "Giving up reconnecting to SpacetimeDB after {} attempts",
+            state.attempt
+        );
+        return;
+    }
+
+    state.attempt += 1;
+    let delay = state.policy.delay(state.attempt);
+    state.next_attempt = Some(Instant::now() + delay);
+    info!(
+        "Reconnecting to SpacetimeDB in {:?} (attempt {})",
+        delay, state.attempt
+    );
+}
+
+/// Rebuilds the connection once the scheduled reconnection attempt is due.
+pub(crate) fn reconnect<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    mut commands: Commands,
+    mut state: ResMut<ReconnectState<C, M>>,
+) {
+    if state.next_attempt.is_none_or(|at| at > Instant::now()) {
+        return;
+    }
+    state.next_attempt = None;
+
+    commands.queue(|world: &mut World| {
+        let token = world.resource::<StdbPluginConfig<C, M>>().token.clone();
+
+        // A failed build is reported like any other connection error, which schedules the next attempt.
+        try_connect::<C, M>(world, token);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_until_the_max_delay() {
+        let policy = ReconnectPolicy::default()
+            .with_initial_delay(Duration::from_millis(500))
+            .with_max_delay(Duration::from_secs(4))
+            .with_jitter(0.0);
+
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(1));
+        assert_eq!(policy.delay(4), Duration::from_secs(4));
+        assert_eq!(policy.delay(5), Duration::from_secs(4));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn delay_jitter_stays_within_bounds() {
+        let policy = ReconnectPolicy::default()
+            .with_initial_delay(Duration::from_secs(1))
+            .with_max_delay(Duration::from_secs(8))
+            .with_jitter(0.25);
+        let exact = policy.clone().with_jitter(0.0);
+
+        for attempt in 1..=100 {
+            let max = exact.delay(attempt).as_secs_f32();
+            let delay = policy.delay(attempt).as_secs_f32();
+            assert!(delay <= max, "attempt {attempt}: {delay} > {max}");
+            assert!(delay >= max * 0.75 - f32::EPSILON, "attempt {attempt}: {delay} < {max} * 0.75");
+        }
+    }
+
+    #[test]
+    fn delay_never_shrinks_below_the_initial_delay_without_jitter() {
+        let policy = ReconnectPolicy::default()
+            .with_initial_delay(Duration::from_secs(2))
+            .with_multiplier(0.5)
+            .with_jitter(0.0);
+
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    marker::PhantomData,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use bevy::{
+    app::{App, PostUpdate},
+    ecs::message::{MessageCursor, Messages},
+    platform::time::Instant,
+    prelude::{IntoScheduleConfigs, Local, MessageWriter, Res, ResMut, Resource, World},
+};
+use spacetimedb_sdk::{__codegen as spacetime_codegen, DbContext, Error, ReducerEvent, Status};
+
+use crate::{
+    CallReducer, ReadStdbDisconnectedMessage, ReducerCallFailedMessage, ReducerCallResultMessage,
+    ReducerCallTimeoutMessage, StdbConnectedMessage, StdbConnection, StdbPlugin, StdbSequence,
+    plugin::{MessageSenders, message_sender},
+};
+
+/// Identifies a reducer call made with a [`CallReducer`] message, to match it with the messages reporting its outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReducerCallId(u64);
+
+impl ReducerCallId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The outcome of a reducer call, carried by its [`ReducerCallResultMessage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReducerCallOutcome {
+    /// The reducer ran and its transaction was committed.
+    Committed,
+    /// The reducer returned an error or panicked, with its error message.
+    Failed(String),
+    /// The reducer ran out of energy.
+    OutOfEnergy,
+    /// The connection was closed before the result arrived, the reducer may or may not have run.
+    Disconnected,
+}
+
+impl ReducerCallOutcome {
+    fn of(status: &Status) -> Self {
+        match status {
+            Status::Committed => Self::Committed,
+            Status::Failed(message) => Self::Failed(message.to_string()),
+            Status::OutOfEnergy => Self::OutOfEnergy,
+        }
+    }
+}
+
+/// Trait for calling a reducer from a [`CallReducer`] message.
+///
+/// Can be derived with `#[derive(CallableReducer)]` when the `macros` feature is enabled.
+///
+/// ## Example
+///
+/// ```no-run
+/// pub struct MovePlayer {
+///     pub x: f32,
+///     pub y: f32,
+/// }
+///
+/// impl CallableReducer<DbConnection, RemoteModule> for MovePlayer {
+///     fn call(&self, reducers: &RemoteReducers) -> spacetimedb_sdk::Result<()> {
+///         reducers.move_player(self.x, self.y)
+///     }
+///
+///     fn on_call_result(reducers: &RemoteReducers, mut callback: Box<dyn FnMut(&ReducerEvent<Reducer>) + Send>) {
+///         reducers.on_move_player(move |ctx, _, _| {
+///             if ctx.event.caller_connection_id == Some(ctx.connection_id()) {
+///                 callback(&ctx.event);
+///             }
+///         });
+///     }
+/// }
+/// ```
+pub trait CallableReducer<
+    C: spacetime_codegen::DbConnection<Module = M> + DbContext,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+>: Send + Sync + 'static
+{
+    /// Calls the reducer with the arguments held by `self`.
+    fn call(&self, reducers: &C::Reducers) -> spacetimedb_sdk::Result<()>;
+
+    /// Registers `callback`, called with the event of every invocation of the reducer made by this connection.
+    #[allow(clippy::type_complexity)]
+    fn on_call_result(
+        reducers: &C::Reducers,
+        callback: Box<dyn FnMut(&ReducerEvent<M::Reducer>) + Send>,
+    );
+}
+
+/// What happens to the reducer calls made while disconnected, see [`StdbPlugin::add_reducer_call_with_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReducerCallPolicy {
+    /// Fails the calls with a [`ReducerCallFailedMessage`].
+    Drop,
+    /// Keeps the latest call, the calls it replaces fail with a [`ReducerCallFailedMessage`].
+    KeepLatest,
+    /// Keeps every call.
+    KeepAll,
+    /// Keeps every call, those older than the given duration when the connection is established
+    /// fail with a [`ReducerCallFailedMessage`].
+    ExpireAfter(Duration),
+}
+
+/// The policy of the reducer `R`, as a Bevy resource.
+#[derive(Resource)]
+struct StdbReducerCallPolicy<R: Send + Sync + 'static> {
+    policy: ReducerCallPolicy,
+    _phantom: PhantomData<R>,
+}
+
+/// How long to wait for the result of a reducer call, see [`StdbPlugin::with_reducer_call_timeout`].
+#[derive(Resource)]
+pub(crate) struct StdbReducerCallTimeout<C: Send + Sync + 'static> {
+    pub(crate) timeout: Duration,
+    pub(crate) _phantom: PhantomData<C>,
+}
+
+/// A reducer call sent to SpacetimeDB, waiting for its result.
+struct PendingCall {
+    id: ReducerCallId,
+    connection: u64,
+    sent_at: Instant,
+    timed_out: bool,
+}
+
+/// The calls of a reducer waiting for their result, shared with the reducer callback.
+///
+/// SpacetimeDB runs the calls of a connection in order, so the results arrive in the order the calls were sent.
+#[derive(Default)]
+struct PendingCalls {
+    /// Incremented on every new connection, the calls sent on a previous one never get a result.
+    connection: AtomicU64,
+    calls: Mutex<VecDeque<PendingCall>>,
+}
+
+impl PendingCalls {
+    /// Calls the reducer with `call`, and waits for its result if it was sent.
+    fn send(
+        &self,
+        id: ReducerCallId,
+        call: impl FnOnce() -> spacetimedb_sdk::Result<()>,
+    ) -> spacetimedb_sdk::Result<()> {
+        // Locked before calling, so the result can't arrive before the call is pending
+        let mut calls = self.calls.lock().unwrap();
+        calls.push_back(PendingCall {
+            id,
+            connection: self.connection.load(Ordering::Relaxed),
+            sent_at: Instant::now(),
+            timed_out: false,
+        });
+
+        let result = call();
+        if result.is_err() {
+            calls.pop_back();
+        }
+        result
+    }
+
+    /// Returns the id of the call the result received on the given connection belongs to.
+    fn complete(&self, connection: u64) -> Option<ReducerCallId> {
+        let mut calls = self.calls.lock().unwrap();
+        while calls.front().is_some_and(|call| call.connection != connection) {
+            calls.pop_front();
+        }
+        calls.pop_front().map(|call| call.id)
+    }
+
+    /// Removes the calls that will never get a result, i.e. those not sent on `connection`,
+    /// or every call when the connection is closed.
+    fn abandon(&self, connection: Option<u64>) -> Vec<ReducerCallId> {
+        let mut calls = self.calls.lock().unwrap();
+        let (abandoned, kept) = std::mem::take(&mut *calls)
+            .into_iter()
+            .partition(|call| Some(call.connection) != connection);
+        *calls = kept;
+        abandoned.into_iter().map(|call| call.id).collect()
+    }
+}
+
+/// The [`PendingCalls`] of the reducer `R`, as a Bevy resource.
+#[derive(Resource)]
+struct StdbPendingReducerCalls<R: Send + Sync + 'static> {
+    calls: Arc<PendingCalls>,
+    _phantom: PhantomData<R>,
+}
+
+/// A reducer call made while disconnected, waiting for the connection to be established.
+struct QueuedCall {
+    call: Box<dyn Any + Send + Sync>,
+    queued_at: Instant,
+    expire_after: Option<Duration>,
+    /// Calls the reducer, or reports it as failed with the given error.
+    send: fn(&mut World, Box<dyn Any + Send + Sync>, Option<Error>),
+}
+
+/// The reducer calls made while the connection `C` is down, replayed in order once it is established.
+#[derive(Resource)]
+struct StdbReducerQueue<C: Send + Sync + 'static> {
+    calls: Vec<QueuedCall>,
+    /// The generation of the last connection whose [`StdbConnectedMessage`] has been received,
+    /// the calls are only replayed once it is the current connection.
+    connected: Option<u64>,
+    _phantom: PhantomData<C>,
+}
+
+impl<C: Send + Sync + 'static> Default for StdbReducerQueue<C> {
+    fn default() -> Self {
+        Self {
+            calls: Vec::new(),
+            connected: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<
+    C: spacetime_codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+> StdbPlugin<C, M>
+{
+    /// Registers the [`CallReducer<R>`] message, calling the reducer `R` for every message written by your systems.
+    ///
+    /// Calls that can't be sent, e.g. while disconnected, are reported as a [`ReducerCallFailedMessage<R>`],
+    /// and the result of the others as a [`ReducerCallResultMessage<R>`].
+    /// Call the reducer only through [`CallReducer<R>`] messages, so the results are matched with the right call.
+    pub fn add_reducer_call<R: CallableReducer<C, M>>(self) -> Self {
+        self.add_reducer_call_with_policy::<R>(ReducerCallPolicy::Drop)
+    }
+
+    /// Registers the [`CallReducer<R>`] message like [`StdbPlugin::add_reducer_call`], and queues the calls made
+    /// while disconnected (including before the first connection) according to `policy`.
+    ///
+    /// The queued calls of every reducer are replayed in order once the [`StdbConnectedMessage`] is received.
+    pub fn add_reducer_call_with_policy<R: CallableReducer<C, M>>(
+        self,
+        policy: ReducerCallPolicy,
+    ) -> Self {
+        self.register_channel::<ReducerCallResultMessage<R>>();
+        let pending = Arc::new(PendingCalls::default());
+
+        // This callback reports the results of the calls, registered on every new connection.
+        let callback_pending = Arc::clone(&pending);
+        let register_callback = move |senders: &MessageSenders, reducers: &C::Reducers| {
+            let pending = Arc::clone(&callback_pending);
+            let connection = pending.connection.fetch_add(1, Ordering::Relaxed) + 1;
+            let sender = message_sender::<ReducerCallResultMessage<R>>(senders);
+
+            // The calls sent on the previous connection are over, in case its disconnection wasn't received
+            for id in pending.abandon(Some(connection)) {
+                let _ = sender.send(ReducerCallResultMessage {
+                    sequence: StdbSequence::next(),
+                    id,
+                    outcome: ReducerCallOutcome::Disconnected,
+                    _phantom: PhantomData,
+                });
+            }
+
+            R::on_call_result(
+                reducers,
+                Box::new(move |event| {
+                    if let Some(id) = pending.complete(connection) {
+                        let _ = sender.send(ReducerCallResultMessage {
+                            sequence: StdbSequence::next(),
+                            id,
+                            outcome: ReducerCallOutcome::of(&event.status),
+                            _phantom: PhantomData,
+                        });
+                    }
+                }),
+            );
+        };
+        self.reducer_registers.lock().unwrap().push(Box::new(register_callback));
+
+        // This callback adds the system calling the reducer when the plugin is built.
+        let register_fn = move |app: &mut App| {
+            // The queue is shared by every reducer of the connection
+            if !app.world().contains_resource::<StdbReducerQueue<C>>() {
+                app.init_resource::<StdbReducerQueue<C>>()
+                    .add_systems(PostUpdate, replay_reducer_calls::<C>);
+            }
+
+            app.add_message::<CallReducer<R>>()
+                .add_message::<ReducerCallFailedMessage<R>>()
+                .add_message::<ReducerCallTimeoutMessage<R>>()
+                .insert_resource(StdbReducerCallPolicy::<R> {
+                    policy,
+                    _phantom: PhantomData,
+                })
+                .insert_resource(StdbPendingReducerCalls::<R> {
+                    calls: Arc::clone(&pending),
+                    _phantom: PhantomData,
+                })
+                .add_systems(
+                    PostUpdate,
+                    (
+                        call_reducers::<C, M, R>.after(replay_reducer_calls::<C>),
+                        time_out_reducer_calls::<C, R>,
+                        abandon_reducer_calls::<C, R>,
+                    ),
+                );
+        };
+
+        // The register_fn will get called once, when the plugin is built.
+        self.system_registers.lock().unwrap().push(Box::new(register_fn));
+
+        self
+    }
+}
+
+/// Drains the [`CallReducer<R>`] messages and calls the reducer for each of them,
+/// or queues them according to the policy of the reducer while disconnected.
+fn call_reducers<C, M, R>(
+    conn: Option<Res<StdbConnection<C>>>,
+    policy: Res<StdbReducerCallPolicy<R>>,
+    pending: Res<StdbPendingReducerCalls<R>>,
+    mut queue: ResMut<StdbReducerQueue<C>>,
+    mut calls: ResMut<Messages<CallReducer<R>>>,
+    mut failed: MessageWriter<ReducerCallFailedMessage<R>>,
+) where
+    C: spacetime_codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    R: CallableReducer<C, M>,
+{
+    let mut fail = |call: CallReducer<R>, err: Error| {
+        failed.write(ReducerCallFailedMessage {
+            sequence: StdbSequence::next(),
+            id: call.id,
+            reducer: call.reducer,
+            err,
+        });
+    };
+
+    for call in calls.drain() {
+        let conn = conn.as_ref().filter(|conn| match policy.policy {
+            // Calls that are never queued are sent as soon as the connection is active
+            ReducerCallPolicy::Drop => conn.is_active(),
+            // Calls wait for the connection to be established, and behind the queued ones to keep their order
+            _ => {
+                conn.is_active()
+                    && queue.connected == Some(conn.generation())
+                    && queue.calls.is_empty()
+            }
+        });
+        if let Some(conn) = conn {
+            if let Err(err) = pending.calls.send(call.id, || call.reducer.call(conn.reducers())) {
+                fail(call, err);
+            }
+            continue;
+        }
+
+        let expire_after = match policy.policy {
+            ReducerCallPolicy::Drop => {
+                fail(call, Error::Disconnected);
+                continue;
+            }
+            ReducerCallPolicy::KeepLatest => {
+                let (replaced, kept) = std::mem::take(&mut queue.calls)
+                    .into_iter()
+                    .partition(|queued| queued.call.is::<CallReducer<R>>());
+                queue.calls = kept;
+                for queued in replaced {
+                    if let Ok(replaced) = queued.call.downcast::<CallReducer<R>>() {
+                        fail(*replaced, Error::Disconnected);
+                    }
+                }
+                None
+            }
+            ReducerCallPolicy::KeepAll => None,
+            ReducerCallPolicy::ExpireAfter(duration) => Some(duration),
+        };
+
+        queue.calls.push(QueuedCall {
+            call: Box::new(call),
+            queued_at: Instant::now(),
+            expire_after,
+            send: send_queued_call::<C, M, R>,
+        });
+    }
+}
+
+/// Calls a queued reducer, or reports it as failed with `err`.
+fn send_queued_call<C, M, R>(world: &mut World, call: Box<dyn Any + Send + Sync>, err: Option<Error>)
+where
+    C: spacetime_codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    R: CallableReducer<C, M>,
+{
+    let Ok(call) = call.downcast::<CallReducer<R>>() else {
+        return;
+    };
+
+    let pending = world.resource::<StdbPendingReducerCalls<R>>();
+    let result = match (err, world.get_resource::<StdbConnection<C>>()) {
+        (Some(err), _) => Err(err),
+        (None, Some(conn)) => pending
+            .calls
+            .send(call.id, || call.reducer.call(conn.reducers())),
+        (None, None) => Err(Error::Disconnected),
+    };
+
+    if let Err(err) = result {
+        world.write_message(ReducerCallFailedMessage {
+            sequence: StdbSequence::next(),
+            id: call.id,
+            reducer: call.reducer,
+            err,
+        });
+    }
+}
+
+/// Replays the queued reducer calls in order once the [`StdbConnectedMessage`] of the connection is received.
+fn replay_reducer_calls<C: spacetime_codegen::DbConnection + DbContext + Send + Sync>(
+    world: &mut World,
+    mut cursor: Local<MessageCursor<StdbConnectedMessage<C>>>,
+) {
+    let connected: Vec<u64> = cursor
+        .read(world.resource::<Messages<StdbConnectedMessage<C>>>())
+        .map(|message| message.generation)
+        .collect();
+    let Some(generation) = world
+        .get_resource::<StdbConnection<C>>()
+        .filter(|conn| conn.is_active())
+        .map(|conn| conn.generation())
+    else {
+        return;
+    };
+
+    // A connection replaced within a frame stays active, its generation tells it apart from the new one
+    let mut queue = world.resource_mut::<StdbReducerQueue<C>>();
+    if connected.contains(&generation) {
+        queue.connected = Some(generation);
+    }
+    if queue.connected != Some(generation) {
+        return;
+    }
+
+    let now = Instant::now();
+    for queued in std::mem::take(&mut queue.calls) {
+        let expired = queued
+            .expire_after
+            .is_some_and(|expire_after| now.duration_since(queued.queued_at) > expire_after);
+        (queued.send)(world, queued.call, expired.then_some(Error::Disconnected));
+    }
+}
+
+/// Emits a [`ReducerCallResultMessage`] with [`ReducerCallOutcome::Disconnected`] for the calls of the reducer `R`
+/// that were waiting for their result when the connection was closed.
+fn abandon_reducer_calls<C, R>(
+    conn: Option<Res<StdbConnection<C>>>,
+    pending: Res<StdbPendingReducerCalls<R>>,
+    mut disconnected: ReadStdbDisconnectedMessage<C>,
+    mut writer: MessageWriter<ReducerCallResultMessage<R>>,
+) where
+    C: spacetime_codegen::DbConnection + DbContext + Send + Sync,
+    R: Send + Sync + 'static,
+{
+    if disconnected.read().count() == 0 {
+        return;
+    }
+
+    // A new connection may already be established, its calls are still waiting
+    let connection = conn
+        .filter(|conn| conn.is_active())
+        .map(|_| pending.calls.connection.load(Ordering::Relaxed));
+    for id in pending.calls.abandon(connection) {
+        writer.write(ReducerCallResultMessage {
+            sequence: StdbSequence::next(),
+            id,
+            outcome: ReducerCallOutcome::Disconnected,
+            _phantom: PhantomData,
+        });
+    }
+}
+
+/// Emits a [`ReducerCallTimeoutMessage`] for the calls of the reducer `R` waiting for their result
+/// for longer than the timeout.
+fn time_out_reducer_calls<C: Send + Sync + 'static, R: Send + Sync + 'static>(
+    timeout: Option<Res<StdbReducerCallTimeout<C>>>,
+    pending: Res<StdbPendingReducerCalls<R>>,
+    mut writer: MessageWriter<ReducerCallTimeoutMessage<R>>,
+) {
+    let Some(timeout) = timeout else {
+        return;
+    };
+
+    let now = Instant::now();
+    let connection = pending.calls.connection.load(Ordering::Relaxed);
+    let mut calls = pending.calls.calls.lock().unwrap();
+    for call in calls.iter_mut() {
+        if !call.timed_out && now.duration_since(call.sent_at) > timeout.timeout {
+            call.timed_out = true;
+            writer.write(ReducerCallTimeoutMessage {
+                sequence: StdbSequence::next(),
+                id: call.id,
+                _phantom: PhantomData,
+            });
+        }
+    }
+
+    // The calls sent on a previous connection never get a result
+    calls.retain(|call| call.connection == connection || !call.timed_out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(pending: &PendingCalls) -> ReducerCallId {
+        let id = ReducerCallId::next();
+        pending.send(id, || Ok(())).unwrap();
+        id
+    }
+
+    #[test]
+    fn results_complete_the_calls_in_order() {
+        let pending = PendingCalls::default();
+        let (first, second) = (send(&pending), send(&pending));
+
+        assert_eq!(pending.complete(0), Some(first));
+        assert_eq!(pending.complete(0), Some(second));
+        assert_eq!(pending.complete(0), None);
+    }
+
+    #[test]
+    fn calls_that_failed_to_send_are_not_pending() {
+        let pending = PendingCalls::default();
+        let id = ReducerCallId::next();
+
+        assert!(pending.send(id, || Err(Error::Disconnected)).is_err());
+        assert_eq!(pending.complete(0), None);
+    }
+
+    #[test]
+    fn results_skip_the_calls_of_previous_connections() {
+        let pending = PendingCalls::default();
+        send(&pending);
+        pending.connection.store(1, Ordering::Relaxed);
+        let current = send(&pending);
+
+        assert_eq!(pending.complete(1), Some(current));
+        assert!(pending.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn abandon_keeps_the_calls_of_the_current_connection() {
+        let pending = PendingCalls::default();
+        let previous = send(&pending);
+        pending.connection.store(1, Ordering::Relaxed);
+        let current = send(&pending);
+
+        assert_eq!(pending.abandon(Some(1)), vec![previous]);
+        assert_eq!(pending.abandon(None), vec![current]);
+        assert_eq!(pending.complete(1), None);
+    }
+}
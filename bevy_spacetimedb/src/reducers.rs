@@ -1,7 +1,9 @@
-use crate::{AddMessageChannelAppExtensions, ReducerResultMessage, StdbPlugin};
-use bevy::app::App;
+use crate::{
+    ReducerResultMessage, StdbPlugin,
+    plugin::{MessageSenders, message_sender},
+};
 use spacetimedb_sdk::__codegen as spacetime_codegen;
-use std::sync::mpsc::{Sender, channel};
+use std::sync::mpsc::Sender;
 
 /// Trait for making a reducer registerable into the bevy application.
 pub trait RegisterableReducerMessage<
@@ -21,16 +23,16 @@ impl<
 {
     /// Registers a reducer message <E> for the bevy application.
     pub fn add_reducer<E: RegisterableReducerMessage<C, M> + Send + Sync + 'static>(
-        mut self,
+        self,
     ) -> Self {
+        self.register_channel::<ReducerResultMessage<E>>();
+
         // This callback manages the registration of the message.
-        let register_fn = move |app: &mut App, reducers: &C::Reducers| {
-            let (send, recv) = channel::<ReducerResultMessage<E>>();
-            app.add_message_channel(recv);
-            E::set_stdb_callback(reducers, send);
+        let register_fn = move |senders: &MessageSenders, reducers: &C::Reducers| {
+            E::set_stdb_callback(reducers, message_sender::<ReducerResultMessage<E>>(senders));
         };
 
-        // The register_fn will get called once the connection is built.
+        // The register_fn will get called every time a connection is built.
         self.reducer_registers.lock().unwrap().push(Box::new(register_fn));
 
         self
@@ -0,0 +1,222 @@
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::tasks::tick_global_task_pools_on_main_thread;
+use bevy::{
+    app::AppExit,
+    log::{error, warn},
+    platform::time::Instant,
+    prelude::{Commands, MessageReader, Res, Resource, World},
+    tasks::{IoTaskPool, Task, TaskPool},
+};
+use spacetimedb_sdk::DbContext;
+
+use crate::{StdbConnection, StdbPluginConfig, transactions::StdbTransactions};
+
+/// A function returning the future that processes the connection's messages, see [`StdbRunMode::Async`].
+pub type AsyncRunFn<C> = Arc<
+    dyn Fn(Arc<C>) -> Pin<Box<dyn Future<Output = spacetimedb_sdk::Result<()>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// How the connection processes the messages it receives from SpacetimeDB.
+pub enum StdbRunMode<C> {
+    /// Process messages on a dedicated thread, usually `DbConnection::run_threaded`.
+    ///
+    /// Callbacks run on that thread and reach Bevy through channels.
+    Threaded(fn(&C) -> JoinHandle<()>),
+    /// Process the pending messages once per run of a Bevy schedule, usually `DbConnection::frame_tick`.
+    ///
+    /// Callbacks run on the main schedule and no background thread is spawned.
+    FrameTick(fn(&C) -> spacetimedb_sdk::Result<()>),
+    /// Process messages in a task on Bevy's [`IoTaskPool`], usually by awaiting `DbConnection::run_async`.
+    ///
+    /// The task is exposed as the [`StdbRunTask`] resource.
+    Async(AsyncRunFn<C>),
+}
+
+// Manually implement Clone since deriving it would require `C: Clone`
+impl<C> Clone for StdbRunMode<C> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Threaded(run_fn) => Self::Threaded(*run_fn),
+            Self::FrameTick(tick_fn) => Self::FrameTick(*tick_fn),
+            Self::Async(run_async) => Self::Async(Arc::clone(run_async)),
+        }
+    }
+}
+
+impl<C> StdbRunMode<C> {
+    /// Starts processing messages for a newly built connection,
+    /// inserting the thread or task processing them as a resource.
+    pub(crate) fn start(&self, world: &mut World, conn: &Arc<C>)
+    where
+        C: Send + Sync + 'static,
+    {
+        match self {
+            Self::Threaded(run_fn) => {
+                world.insert_resource(RunThread::<C>(run_fn(conn), PhantomData));
+            }
+            // Driven by the frame_tick system
+            Self::FrameTick(_) => {}
+            Self::Async(run_async) => {
+                let future = run_async(Arc::clone(conn));
+                let task = IoTaskPool::get_or_init(TaskPool::new).spawn(future);
+                world.insert_resource(StdbRunTask::<C>::new(task));
+            }
+        }
+    }
+}
+
+/// The thread processing the connection's messages when running in [`StdbRunMode::Threaded`].
+#[derive(Resource)]
+pub(crate) struct RunThread<C: Send + Sync + 'static>(JoinHandle<()>, PhantomData<C>);
+
+/// The task processing the connection's messages when running in [`StdbRunMode::Async`], as a Bevy resource.
+///
+/// It is replaced along with the [`StdbConnection`]. Removing this resource cancels the task.
+#[derive(Resource)]
+pub struct StdbRunTask<C: Send + Sync + 'static> {
+    task: Task<spacetimedb_sdk::Result<()>>,
+    _phantom: PhantomData<C>,
+}
+
+impl<C: Send + Sync + 'static> StdbRunTask<C> {
+    pub(crate) fn new(task: Task<spacetimedb_sdk::Result<()>>) -> Self {
+        Self {
+            task,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Lets the task run in the background until the connection is closed.
+    pub(crate) fn detach(self) {
+        self.task.detach();
+    }
+
+    /// Returns `true` if the task has finished, which happens once the connection is closed.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+/// Shuts the connection down gracefully when the app exits.
+pub(crate) fn shutdown_on_exit<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    mut exit: MessageReader<AppExit>,
+    mut commands: Commands,
+) {
+    if exit.read().count() == 0 {
+        return;
+    }
+
+    commands.queue(shutdown::<C, M>);
+}
+
+/// Processes the pending messages of the connection when running in [`StdbRunMode::FrameTick`].
+pub(crate) fn frame_tick<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    conn: Option<Res<StdbConnection<C>>>,
+    config: Res<StdbPluginConfig<C, M>>,
+    transactions: Res<StdbTransactions<C>>,
+) {
+    let (Some(conn), StdbRunMode::FrameTick(tick_fn)) = (conn, &config.run_mode) else {
+        return;
+    };
+
+    // Keep ticking an inactive connection so its disconnect callback is delivered
+    if let Err(err) = tick_fn(conn.conn())
+        && conn.is_active()
+    {
+        error!("Failed to process SpacetimeDB messages: {}", err);
+    }
+
+    // Every update received so far has been processed
+    transactions.transactions.complete_current();
+}
+
+/// Disconnects and waits, up to the configured shutdown timeout, for the connection to process the disconnection.
+///
+/// Reducer calls made before the disconnection are sent before the socket is closed,
+/// so the server runs its `client_disconnected` reducer as for any other client.
+fn shutdown<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    world: &mut World,
+) {
+    let Some(conn) = world.remove_resource::<StdbConnection<C>>() else {
+        return;
+    };
+    let thread = world.remove_resource::<RunThread<C>>();
+    let task = world.remove_resource::<StdbRunTask<C>>();
+    let config = world.resource::<StdbPluginConfig<C, M>>();
+
+    if !conn.is_active() {
+        return;
+    }
+    if let Err(err) = conn.disconnect() {
+        warn!("Failed to disconnect from SpacetimeDB: {}", err);
+        return;
+    }
+
+    let deadline = Instant::now() + config.shutdown_timeout;
+    let finished = match &config.run_mode {
+        StdbRunMode::Threaded(_) => wait_until(deadline, || {
+            thread.as_ref().is_none_or(|thread| thread.0.is_finished())
+        }),
+        StdbRunMode::FrameTick(tick_fn) => tick_until_closed(conn.conn(), *tick_fn, deadline),
+        StdbRunMode::Async(_) => wait_until(deadline, || {
+            #[cfg(not(target_arch = "wasm32"))]
+            tick_global_task_pools_on_main_thread();
+            task.as_ref().is_none_or(|task| task.is_finished())
+        }),
+    };
+
+    if !finished {
+        warn!(
+            "SpacetimeDB connection did not shut down within {:?}",
+            config.shutdown_timeout
+        );
+    } else if let Some(thread) = thread {
+        let _ = thread.0.join();
+    }
+}
+
+/// Ticks a connection running in [`StdbRunMode::FrameTick`] until it has processed its disconnection,
+/// or the deadline is reached, returning whether it did.
+pub(crate) fn tick_until_closed<C: DbContext>(
+    conn: &C,
+    tick_fn: fn(&C) -> spacetimedb_sdk::Result<()>,
+    deadline: Instant,
+) -> bool {
+    wait_until(deadline, || {
+        let _ = tick_fn(conn);
+        !conn.is_active()
+    })
+}
+
+/// Calls `done` until it returns `true` or the deadline is reached, returning whether it completed.
+fn wait_until(deadline: Instant, mut done: impl FnMut() -> bool) -> bool {
+    loop {
+        if done() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,86 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::{NextState, Res, ResMut, Resource, States, World};
+use spacetimedb_sdk::DbContext;
+
+use crate::{
+    ReadStdbConnectedMessage, ReadStdbConnectionErrorMessage, ReadStdbDisconnectedMessage,
+    StdbConnection, reconnect::ReconnectState,
+};
+
+/// The state of the connection to SpacetimeDB, registered by the [`StdbPlugin`](crate::StdbPlugin)
+/// when the `states` feature is enabled.
+///
+/// Use it with `OnEnter(StdbConnectionState::Connected)` or `run_if(in_state(StdbConnectionState::Connected))`.
+/// The `StatesPlugin` (included in `DefaultPlugins`) must be added before the `StdbPlugin`.
+///
+/// When several `StdbPlugin`s are added, the state follows the connection of the first one.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum StdbConnectionState {
+    /// No connection has been attempted yet, or the connection was closed.
+    #[default]
+    Disconnected,
+    /// A connection is being established.
+    Connecting,
+    /// The connection is established.
+    Connected,
+    /// The connection was lost and a reconnection attempt is scheduled.
+    Reconnecting,
+    /// The connection failed and no reconnection attempt is scheduled.
+    Failed,
+}
+
+/// Marks the connection `C` as the one driving the [`StdbConnectionState`].
+#[derive(Resource)]
+pub(crate) struct ConnectionStateDriver<C: Send + Sync + 'static>(pub(crate) PhantomData<C>);
+
+/// Sets the next [`StdbConnectionState`], if it is driven by the connection `C`.
+pub(crate) fn set_connection_state<C: Send + Sync + 'static>(
+    world: &mut World,
+    state: StdbConnectionState,
+) {
+    if !world.contains_resource::<ConnectionStateDriver<C>>() {
+        return;
+    }
+    if let Some(mut next_state) = world.get_resource_mut::<NextState<StdbConnectionState>>() {
+        next_state.set(state);
+    }
+}
+
+/// Updates the [`StdbConnectionState`] from the connection messages forwarded by the channel bridge.
+pub(crate) fn update_connection_state<
+    C: spacetimedb_sdk::__codegen::DbConnection<Module = M> + DbContext + Send + Sync,
+    M: spacetimedb_sdk::__codegen::SpacetimeModule<DbConnection = C>,
+>(
+    mut connected: ReadStdbConnectedMessage<C>,
+    mut disconnected: ReadStdbDisconnectedMessage<C>,
+    mut errors: ReadStdbConnectionErrorMessage<C>,
+    reconnect: Option<Res<ReconnectState<C, M>>>,
+    conn: Option<Res<StdbConnection<C>>>,
+    mut next_state: ResMut<NextState<StdbConnectionState>>,
+) {
+    let reconnecting = reconnect.is_some_and(|reconnect| reconnect.is_pending());
+    // Messages from a replaced connection are ignored while the current one is active
+    let replaced = conn.is_some_and(|conn| conn.is_active());
+    let failed = errors.read().count() > 0 && !replaced;
+    let lost = disconnected.read().count() > 0 && !replaced;
+
+    if failed {
+        next_state.set(if reconnecting {
+            StdbConnectionState::Reconnecting
+        } else {
+            StdbConnectionState::Failed
+        });
+    } else if lost {
+        next_state.set(if reconnecting {
+            StdbConnectionState::Reconnecting
+        } else {
+            StdbConnectionState::Disconnected
+        });
+    }
+
+    // A connection established in the same frame takes precedence over an earlier failure.
+    if connected.read().count() > 0 {
+        next_state.set(StdbConnectionState::Connected);
+    }
+}
@@ -1,18 +1,43 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
 use bevy::prelude::Resource;
 use spacetimedb_sdk::{ConnectionId, DbContext, Identity, Result};
 
 #[derive(Resource)]
 /// A connection to the SpacetimeDB server, as a Bevy resource.
 /// This struct is a wrapper around a concrete-typed `DbContext` in your auto-generated.
+///
+/// The connection is owned by the resource: once it is disconnected and the resource is removed,
+/// the client cache and every callback registered on it are freed.
 pub struct StdbConnection<T: DbContext + 'static> {
     /// The underlying connection.
-    conn: &'static T,
+    conn: Arc<T>,
+    /// Tells this connection apart from the previous and next ones.
+    generation: u64,
+}
+
+/// Returns a generation no connection had so far, see [`StdbConnection::generation`].
+pub(crate) fn next_generation() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
 }
 
 impl<T: DbContext> StdbConnection<T> {
     /// Create a new connection to the SpacetimeDB server.
-    pub fn new(conn: &'static T) -> Self {
-        Self { conn }
+    pub fn new(conn: Arc<T>) -> Self {
+        Self::with_generation(conn, next_generation())
+    }
+
+    pub(crate) fn with_generation(conn: Arc<T>, generation: u64) -> Self {
+        Self { conn, generation }
+    }
+
+    /// Identifies the connection, to match it with its [`StdbConnectedMessage`](crate::StdbConnectedMessage).
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
     }
 }
 
@@ -69,6 +94,6 @@ impl<T: DbContext> StdbConnection<T> {
 
     /// Access to the underlying connection, it's not recommended to use this method directly.
     pub fn conn(&self) -> &T {
-        self.conn
+        &self.conn
     }
 }
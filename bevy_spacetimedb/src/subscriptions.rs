@@ -0,0 +1,576 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::Sender,
+    },
+};
+
+use bevy::{
+    log::warn,
+    platform::collections::HashMap,
+    prelude::{Mut, ResMut, Resource, World},
+};
+#[cfg(feature = "states")]
+use bevy::{
+    app::{App, PreUpdate},
+    prelude::{Commands, IntoScheduleConfigs, OnEnter, OnExit, Res, States, in_state},
+};
+use spacetimedb_sdk::{
+    __codegen as spacetime_codegen, DbContext, Error, SubscriptionBuilder, SubscriptionHandle,
+};
+
+use crate::{
+    ReadSubscriptionAppliedMessage, ReadSubscriptionEndedMessage, ReadSubscriptionErrorMessage,
+    StdbConnection, StdbPlugin, StdbSequence, SubscriptionAppliedMessage, SubscriptionEndedMessage,
+    SubscriptionErrorMessage,
+    plugin::{MessageSenders, message_sender},
+};
+#[cfg(feature = "states")]
+use crate::{MessageChannelSystems, ReadStdbConnectedMessage, StdbCommandsExt};
+
+/// Identifies a subscription in the subscription messages and the [`StdbSubscriptions`] resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StdbSubscriptionId(u64);
+
+impl StdbSubscriptionId {
+    pub(crate) fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The state of a subscription tracked by the [`StdbSubscriptions`] resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StdbSubscriptionState {
+    /// The subscription was sent and the server has not applied it yet.
+    Pending,
+    /// The rows matching the queries are in the client cache.
+    Applied,
+    /// The subscription was unsubscribed, or its connection was closed.
+    Ended,
+    /// The subscription failed or was rejected by the server.
+    Error,
+}
+
+type Handle<C> = <<C as spacetime_codegen::DbConnection>::Module as spacetime_codegen::SpacetimeModule>::SubscriptionHandle;
+
+struct TrackedSubscription<C: spacetime_codegen::DbConnection> {
+    queries: Vec<String>,
+    state: StdbSubscriptionState,
+    // Behind a mutex since SDK handles are only required to be Send
+    handle: Option<Mutex<Handle<C>>>,
+    unsubscribe_on_applied: bool,
+}
+
+/// Tracks the subscriptions made at runtime with
+/// [`StdbCommandsExt::stdb_subscribe`](crate::StdbCommandsExt::stdb_subscribe), as a Bevy resource.
+///
+/// Their state is updated from the [`SubscriptionAppliedMessage<C>`], [`SubscriptionErrorMessage<C>`]
+/// and [`SubscriptionEndedMessage<C>`] messages, right after the channel bridge forwards them.
+/// Subscriptions registered on the plugin with [`StdbPlugin::add_subscription`] are not tracked.
+#[derive(Resource)]
+pub struct StdbSubscriptions<C: spacetime_codegen::DbConnection + DbContext + Send + Sync> {
+    subscriptions: HashMap<StdbSubscriptionId, TrackedSubscription<C>>,
+    send_applied: Sender<SubscriptionAppliedMessage<C>>,
+    send_error: Sender<SubscriptionErrorMessage<C>>,
+    send_ended: Sender<SubscriptionEndedMessage<C>>,
+}
+
+impl<C: spacetime_codegen::DbConnection + DbContext + Send + Sync> StdbSubscriptions<C> {
+    pub(crate) fn new(senders: &MessageSenders) -> Self {
+        Self {
+            subscriptions: HashMap::default(),
+            send_applied: message_sender(senders),
+            send_error: message_sender(senders),
+            send_ended: message_sender(senders),
+        }
+    }
+
+    /// Returns the state of the subscription, or `None` if it is not tracked.
+    pub fn state(&self, id: StdbSubscriptionId) -> Option<StdbSubscriptionState> {
+        self.subscriptions.get(&id).map(|subscription| subscription.state)
+    }
+
+    /// Returns the queries of the subscription, or `None` if it is not tracked.
+    pub fn queries(&self, id: StdbSubscriptionId) -> Option<&[String]> {
+        self.subscriptions
+            .get(&id)
+            .map(|subscription| subscription.queries.as_slice())
+    }
+
+    /// Iterates over the tracked subscriptions and their state.
+    pub fn iter(&self) -> impl Iterator<Item = (StdbSubscriptionId, StdbSubscriptionState)> + '_ {
+        self.subscriptions
+            .iter()
+            .map(|(id, subscription)| (*id, subscription.state))
+    }
+
+    /// Stops tracking the subscriptions that have ended or failed.
+    pub fn clear_ended(&mut self) {
+        self.subscriptions.retain(|_, subscription| {
+            !matches!(
+                subscription.state,
+                StdbSubscriptionState::Ended | StdbSubscriptionState::Error
+            )
+        });
+    }
+
+    /// Subscribes to the queries on the given connection and tracks the subscription as pending.
+    pub(crate) fn subscribe<M>(&mut self, conn: &C, id: StdbSubscriptionId, queries: Vec<String>)
+    where
+        C: spacetime_codegen::DbConnection<Module = M>
+            + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>,
+        M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+    {
+        let handle = subscribe_with_messages(
+            conn,
+            id,
+            queries.clone(),
+            self.send_applied.clone(),
+            self.send_error.clone(),
+        );
+
+        self.subscriptions.insert(
+            id,
+            TrackedSubscription {
+                queries,
+                state: StdbSubscriptionState::Pending,
+                handle: Some(Mutex::new(handle)),
+                unsubscribe_on_applied: false,
+            },
+        );
+    }
+
+    /// Tracks a subscription that could not be sent, and emits its error message.
+    pub(crate) fn fail(&mut self, id: StdbSubscriptionId, queries: Vec<String>, err: Error) {
+        let _ = self.send_error.send(SubscriptionErrorMessage {
+            sequence: StdbSequence::next(),
+            id,
+            queries: queries.clone(),
+            err,
+            _phantom: PhantomData,
+        });
+        self.subscriptions.insert(
+            id,
+            TrackedSubscription {
+                queries,
+                state: StdbSubscriptionState::Error,
+                handle: None,
+                unsubscribe_on_applied: false,
+            },
+        );
+    }
+
+    /// Unsubscribes from the subscription, or once it is applied if it is still pending.
+    pub(crate) fn unsubscribe(&mut self, id: StdbSubscriptionId) {
+        let Some(subscription) = self.subscriptions.get_mut(&id) else {
+            warn!("Tried to unsubscribe from an unknown subscription {:?}", id);
+            return;
+        };
+
+        match subscription.state {
+            StdbSubscriptionState::Pending => subscription.unsubscribe_on_applied = true,
+            StdbSubscriptionState::Applied => {
+                Self::unsubscribe_applied(id, subscription, &self.send_ended)
+            }
+            StdbSubscriptionState::Ended | StdbSubscriptionState::Error => {}
+        }
+    }
+
+    fn unsubscribe_applied(
+        id: StdbSubscriptionId,
+        subscription: &mut TrackedSubscription<C>,
+        send_ended: &Sender<SubscriptionEndedMessage<C>>,
+    ) {
+        let Some(handle) = subscription.handle.take() else {
+            return;
+        };
+
+        let send = send_ended.clone();
+        let queries = subscription.queries.clone();
+        let result = handle.into_inner().unwrap().unsubscribe_then(Box::new(move |_ctx| {
+            let _ = send.send(SubscriptionEndedMessage {
+                sequence: StdbSequence::next(),
+                id,
+                queries,
+                _phantom: PhantomData,
+            });
+        }));
+
+        // The subscription already ended on the server, e.g. because the connection was lost
+        if let Err(err) = result {
+            warn!("Failed to unsubscribe from {:?}: {}", subscription.queries, err);
+            Self::end(id, subscription, send_ended);
+        }
+    }
+
+    /// Marks the subscription as ended and emits its ended message.
+    fn end(
+        id: StdbSubscriptionId,
+        subscription: &mut TrackedSubscription<C>,
+        send_ended: &Sender<SubscriptionEndedMessage<C>>,
+    ) {
+        subscription.state = StdbSubscriptionState::Ended;
+        subscription.handle = None;
+        let _ = send_ended.send(SubscriptionEndedMessage {
+            sequence: StdbSequence::next(),
+            id,
+            queries: subscription.queries.clone(),
+            _phantom: PhantomData,
+        });
+    }
+
+    /// Ends every pending or applied subscription, called when their connection is closed.
+    pub(crate) fn end_all(&mut self) {
+        for (id, subscription) in self.subscriptions.iter_mut() {
+            if matches!(
+                subscription.state,
+                StdbSubscriptionState::Pending | StdbSubscriptionState::Applied
+            ) {
+                Self::end(*id, subscription, &self.send_ended);
+            }
+        }
+    }
+}
+
+/// Tracks the subscriptions registered with [`StdbPlugin::add_state_subscription`] for the state type `S`,
+/// as a Bevy resource.
+///
+/// Holds the id of the subscription made for the current visit of each registered state, whose progress
+/// is tracked by the [`StdbSubscriptions`] resource of its connection.
+#[cfg(feature = "states")]
+#[derive(Resource)]
+pub struct StdbStateSubscriptions<S: States> {
+    // One slot per call to add_state_subscription, in registration order
+    slots: Vec<(S, Option<StdbSubscriptionId>)>,
+}
+
+#[cfg(feature = "states")]
+impl<S: States> Default for StdbStateSubscriptions<S> {
+    fn default() -> Self {
+        Self { slots: Vec::new() }
+    }
+}
+
+#[cfg(feature = "states")]
+impl<S: States> StdbStateSubscriptions<S> {
+    /// Returns the subscriptions made for the current visit of `state`.
+    pub fn get<'a>(&'a self, state: &'a S) -> impl Iterator<Item = StdbSubscriptionId> + 'a {
+        self.slots
+            .iter()
+            .filter(move |(slot_state, _)| slot_state == state)
+            .filter_map(|(_, id)| *id)
+    }
+
+    /// Iterates over the registered states and the subscription made for their current visit, if any.
+    pub fn iter(&self) -> impl Iterator<Item = (&S, Option<StdbSubscriptionId>)> + '_ {
+        self.slots.iter().map(|(state, id)| (state, *id))
+    }
+
+    /// Adds a slot for a subscription following `state`, and returns its index.
+    fn register(&mut self, state: S) -> usize {
+        self.slots.push((state, None));
+        self.slots.len() - 1
+    }
+
+    fn current(&self, slot: usize) -> Option<StdbSubscriptionId> {
+        self.slots[slot].1
+    }
+
+    fn set(&mut self, slot: usize, id: StdbSubscriptionId) {
+        self.slots[slot].1 = Some(id);
+    }
+
+    fn take(&mut self, slot: usize) -> Option<StdbSubscriptionId> {
+        self.slots[slot].1.take()
+    }
+}
+
+/// Subscribes to the queries, forwarding the applied and error callbacks to the given senders.
+fn subscribe_with_messages<
+    S: Send + Sync + 'static,
+    C: spacetime_codegen::DbConnection<Module = M> + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+>(
+    conn: &C,
+    id: StdbSubscriptionId,
+    queries: Vec<String>,
+    send_applied: Sender<SubscriptionAppliedMessage<S>>,
+    send_error: Sender<SubscriptionErrorMessage<S>>,
+) -> M::SubscriptionHandle {
+    let applied_queries = queries.clone();
+    let error_queries = queries.clone();
+
+    conn.subscription_builder()
+        .on_applied(move |_ctx| {
+            let _ = send_applied.send(SubscriptionAppliedMessage {
+                sequence: StdbSequence::next(),
+                id,
+                queries: applied_queries,
+                _phantom: PhantomData,
+            });
+        })
+        .on_error(move |_ctx, err| {
+            let _ = send_error.send(SubscriptionErrorMessage {
+                sequence: StdbSequence::next(),
+                id,
+                queries: error_queries,
+                err,
+                _phantom: PhantomData,
+            });
+        })
+        .subscribe(queries)
+}
+
+/// Subscribes to the queries on the current connection, see [`StdbCommandsExt::stdb_subscribe`](crate::StdbCommandsExt::stdb_subscribe).
+pub(crate) fn subscribe<
+    C: spacetime_codegen::DbConnection<Module = M>
+        + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>
+        + Send
+        + Sync,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+>(
+    world: &mut World,
+    id: StdbSubscriptionId,
+    queries: Vec<String>,
+) {
+    world.resource_scope(|world, mut subscriptions: Mut<StdbSubscriptions<C>>| {
+        match world.get_resource::<StdbConnection<C>>() {
+            Some(conn) if conn.is_active() => subscriptions.subscribe(conn.conn(), id, queries),
+            _ => subscriptions.fail(id, queries, Error::Disconnected),
+        }
+    });
+}
+
+/// Updates the state of the tracked subscriptions from the messages forwarded by the channel bridge.
+pub(crate) fn update_subscriptions<C: spacetime_codegen::DbConnection + DbContext + Send + Sync>(
+    mut applied: ReadSubscriptionAppliedMessage<C>,
+    mut errors: ReadSubscriptionErrorMessage<C>,
+    mut ended: ReadSubscriptionEndedMessage<C>,
+    mut subscriptions: ResMut<StdbSubscriptions<C>>,
+) {
+    let subscriptions = &mut *subscriptions;
+
+    // Late callbacks of a closed connection can't revive a subscription that has ended or failed
+    for message in applied.read() {
+        if let Some(subscription) = subscriptions.subscriptions.get_mut(&message.id)
+            && subscription.state == StdbSubscriptionState::Pending
+        {
+            subscription.state = StdbSubscriptionState::Applied;
+            if subscription.unsubscribe_on_applied {
+                StdbSubscriptions::unsubscribe_applied(
+                    message.id,
+                    subscription,
+                    &subscriptions.send_ended,
+                );
+            }
+        }
+    }
+
+    for message in errors.read() {
+        if let Some(subscription) = subscriptions.subscriptions.get_mut(&message.id)
+            && subscription.state != StdbSubscriptionState::Ended
+        {
+            subscription.state = StdbSubscriptionState::Error;
+            subscription.handle = None;
+        }
+    }
+
+    for message in ended.read() {
+        if let Some(subscription) = subscriptions.subscriptions.get_mut(&message.id)
+            && subscription.state != StdbSubscriptionState::Error
+        {
+            subscription.state = StdbSubscriptionState::Ended;
+            subscription.handle = None;
+        }
+    }
+}
+
+impl<
+    C: spacetime_codegen::DbConnection<Module = M>
+        + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>
+        + Send
+        + Sync,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+> StdbPlugin<C, M>
+{
+    /// Subscribes to the given queries on every connection, including reconnections.
+    ///
+    /// Emits a [`SubscriptionAppliedMessage<C>`] once the rows are in the client cache,
+    /// or a [`SubscriptionErrorMessage<C>`] if the subscription fails.
+    pub fn add_subscription(self, queries: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.add_typed_subscription::<C>(queries)
+    }
+
+    /// Same as [`StdbPlugin::add_subscription`], but the messages are keyed by the marker type `S`,
+    /// e.g. `SubscriptionAppliedMessage<PlayerQueries>`, to tell subscriptions apart.
+    pub fn add_typed_subscription<S: Send + Sync + 'static>(
+        self,
+        queries: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let queries: Vec<String> = queries.into_iter().map(Into::into).collect();
+        // The same id is used on every connection
+        let id = StdbSubscriptionId::next();
+
+        self.register_channel::<SubscriptionAppliedMessage<S>>();
+        self.register_channel::<SubscriptionErrorMessage<S>>();
+
+        // This callback subscribes to the queries on a newly established connection.
+        let register_fn = move |senders: &MessageSenders, conn: &C| {
+            subscribe_with_messages(
+                conn,
+                id,
+                queries.clone(),
+                message_sender::<SubscriptionAppliedMessage<S>>(senders),
+                message_sender::<SubscriptionErrorMessage<S>>(senders),
+            );
+        };
+
+        // The register_fn will get called every time a connection is established.
+        self.subscription_registers
+            .lock()
+            .unwrap()
+            .push(Box::new(register_fn));
+
+        self
+    }
+}
+
+#[cfg(feature = "states")]
+impl<
+    C: spacetime_codegen::DbConnection<Module = M>
+        + DbContext<SubscriptionBuilder = SubscriptionBuilder<M>>
+        + Send
+        + Sync,
+    M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
+> StdbPlugin<C, M>
+{
+    /// Subscribes to the given queries while the app is in the given state.
+    ///
+    /// The subscription is made with [`StdbCommandsExt::stdb_subscribe`](crate::StdbCommandsExt::stdb_subscribe)
+    /// on `OnEnter(state)`, or once connected, and unsubscribed on `OnExit(state)`, so its rows are removed
+    /// from the client cache. It is renewed when the connection is rebuilt while in the state.
+    ///
+    /// The id of the subscription made for the current visit is kept in the [`StdbStateSubscriptions<S>`] resource.
+    pub fn add_state_subscription<S: States>(
+        self,
+        state: S,
+        queries: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let queries: Vec<String> = queries.into_iter().map(Into::into).collect();
+
+        // This callback adds the systems managing the subscription when the plugin is built.
+        let register_fn = move |app: &mut App| {
+            let slot = app
+                .world_mut()
+                .get_resource_or_insert_with(StdbStateSubscriptions::<S>::default)
+                .register(state.clone());
+
+            let enter_queries = queries.clone();
+            let on_enter = move |mut commands: Commands,
+                                 conn: Option<Res<StdbConnection<C>>>,
+                                 mut state_subscriptions: ResMut<StdbStateSubscriptions<S>>| {
+                // Otherwise the subscription is made once connected
+                if conn.is_some_and(|conn| conn.is_active()) {
+                    let id = commands.stdb_subscribe::<C, M>(enter_queries.clone());
+                    state_subscriptions.set(slot, id);
+                }
+            };
+
+            let connected_queries = queries.clone();
+            let on_connected = move |mut commands: Commands,
+                                     mut messages: ReadStdbConnectedMessage<C>,
+                                     subscriptions: Res<StdbSubscriptions<C>>,
+                                     mut state_subscriptions: ResMut<StdbStateSubscriptions<S>>| {
+                // Subscriptions made on a previous connection have ended
+                let subscribed = state_subscriptions
+                    .current(slot)
+                    .and_then(|id| subscriptions.state(id))
+                    .is_some_and(|state| {
+                        matches!(state, StdbSubscriptionState::Pending | StdbSubscriptionState::Applied)
+                    });
+
+                if messages.read().count() > 0 && !subscribed {
+                    let id = commands.stdb_subscribe::<C, M>(connected_queries.clone());
+                    state_subscriptions.set(slot, id);
+                }
+            };
+
+            let on_exit = move |mut commands: Commands,
+                                mut state_subscriptions: ResMut<StdbStateSubscriptions<S>>| {
+                if let Some(id) = state_subscriptions.take(slot) {
+                    commands.stdb_unsubscribe::<C, M>(id);
+                }
+            };
+
+            app.add_systems(OnEnter(state.clone()), on_enter)
+                .add_systems(OnExit(state.clone()), on_exit)
+                .add_systems(
+                    PreUpdate,
+                    on_connected
+                        .after(MessageChannelSystems)
+                        .run_if(in_state(state.clone())),
+                );
+        };
+
+        // The register_fn will get called once, when the plugin is built.
+        self.system_registers
+            .lock()
+            .unwrap()
+            .push(Box::new(register_fn));
+
+        self
+    }
+}
+
+#[cfg(all(test, feature = "states"))]
+mod tests {
+    use super::*;
+
+    #[derive(States, Debug, Clone, PartialEq, Eq, Hash, Default)]
+    enum GameState {
+        #[default]
+        Menu,
+        InMatch,
+    }
+
+    #[test]
+    fn subscriptions_are_looked_up_by_state() {
+        let mut subscriptions = StdbStateSubscriptions::<GameState>::default();
+        let menu = subscriptions.register(GameState::Menu);
+        let (players, items) = (
+            subscriptions.register(GameState::InMatch),
+            subscriptions.register(GameState::InMatch),
+        );
+        let (players_id, items_id) = (StdbSubscriptionId::next(), StdbSubscriptionId::next());
+        subscriptions.set(players, players_id);
+        subscriptions.set(items, items_id);
+
+        let in_match: Vec<_> = subscriptions.get(&GameState::InMatch).collect();
+        assert_eq!(in_match, vec![players_id, items_id]);
+        assert_eq!(subscriptions.get(&GameState::Menu).count(), 0);
+        assert_eq!(subscriptions.current(menu), None);
+    }
+
+    #[test]
+    fn taking_a_subscription_only_clears_its_slot() {
+        let mut subscriptions = StdbStateSubscriptions::<GameState>::default();
+        let (players, items) = (
+            subscriptions.register(GameState::InMatch),
+            subscriptions.register(GameState::InMatch),
+        );
+        let (players_id, items_id) = (StdbSubscriptionId::next(), StdbSubscriptionId::next());
+        subscriptions.set(players, players_id);
+        subscriptions.set(items, items_id);
+
+        assert_eq!(subscriptions.take(players), Some(players_id));
+        assert_eq!(subscriptions.take(players), None);
+        assert_eq!(subscriptions.current(items), Some(items_id));
+        assert_eq!(
+            subscriptions.iter().collect::<Vec<_>>(),
+            vec![(&GameState::InMatch, None), (&GameState::InMatch, Some(items_id))]
+        );
+    }
+}
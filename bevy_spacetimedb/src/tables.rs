@@ -1,14 +1,18 @@
-use std::{
-    any::TypeId,
-    sync::mpsc::{Sender, channel},
+use std::{marker::PhantomData, sync::Arc};
+
+use bevy::{
+    app::{App, PreUpdate},
+    prelude::{DetectChanges, DetectChangesMut, IntoScheduleConfigs, Message, MessageReader, Res, ResMut, Resource},
 };
 
-use crate::AddMessageChannelAppExtensions;
-use bevy::app::App;
+use crate::plugin::{MessageSenders, message_sender};
+use crate::{MessageChannelSystems, transactions::Transactions};
 use spacetimedb_sdk::{__codegen as spacetime_codegen, Event, Table, TableWithPrimaryKey};
 // Imports are marked as unused but they are useful for linking types in docs.
 // #[allow(unused_imports)]
-use crate::{DeleteMessage, InsertMessage, InsertUpdateMessage, StdbPlugin, UpdateMessage};
+use crate::{
+    DeleteMessage, InsertMessage, InsertUpdateMessage, StdbPlugin, StdbSequence, UpdateMessage,
+};
 
 pub trait TableMessage where Self:Sized {
     type Row : Send + Sync + Clone + 'static;
@@ -23,11 +27,14 @@ where
 {
     type Row: Send + Sync + Clone + 'static;
     type Reducer: Send + Sync + Clone + 'static;
-    type Table: Table<Row = Self::Row> + TableWithPrimaryKey<Row = Self::Row>;
+    type EventContext;
+    /// The table handle, borrowing the `DbView` it is accessed from.
+    type Table<'a>: Table<Row = Self::Row, EventContext = Self::EventContext>
+        + TableWithPrimaryKey<Row = Self::Row>;
     type Message: TableMessage<Row = Self::Row, Reducer = Self::Reducer>;
 
-    fn table_accessor(db_context: &'static C::DbView) -> Self::Table;
-    fn context_event_accessor(ctx: &<Self::Table as Table>::EventContext) -> Event<Self::Reducer>;
+    fn table_accessor(db_context: &C::DbView) -> Self::Table<'_>;
+    fn context_event_accessor(ctx: &Self::EventContext) -> Event<Self::Reducer>;
 }
 
 pub trait RegisterableTableWithoutPk<C, M>
@@ -38,11 +45,13 @@ where
 {
     type Row: Send + Sync + Clone + 'static;
     type Reducer: Send + Sync + Clone + 'static;
-    type Table: Table<Row = Self::Row>;
+    type EventContext;
+    /// The table handle, borrowing the `DbView` it is accessed from.
+    type Table<'a>: Table<Row = Self::Row, EventContext = Self::EventContext>;
     type Message: TableMessage<Row = Self::Row, Reducer = Self::Reducer>;
 
-    fn table_accessor(db_context: &'static C::DbView) -> Self::Table;
-    fn context_event_accessor(ctx: &<Self::Table as Table>::EventContext) -> Event<Self::Reducer>;
+    fn table_accessor(db_context: &C::DbView) -> Self::Table<'_>;
+    fn context_event_accessor(ctx: &Self::EventContext) -> Event<Self::Reducer>;
 }
 
 /// Passed into [`StdbPlugin::add_table`] to determine which table messages to register.
@@ -101,6 +110,73 @@ impl TableMessagesWithoutPrimaryKey {
     }
 }
 
+/// Marked as changed, in Bevy's change detection sense, in every frame where a row of the table `T`
+/// was inserted, updated or deleted.
+///
+/// Use it with `Res<StdbTableChanged<T>>::is_changed()` or the [`stdb_table_changed`] run condition,
+/// without reading the table messages.
+#[derive(Resource)]
+pub struct StdbTableChanged<T: TableMessage + 'static> {
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: TableMessage + 'static> Default for StdbTableChanged<T> {
+    fn default() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// A run condition that is `true` when a row of the table `T` was inserted, updated or deleted this frame.
+///
+/// ```no-run
+/// app.add_systems(Update, rebuild_player_list.run_if(stdb_table_changed::<PlayersTable>()));
+/// ```
+pub fn stdb_table_changed<T: TableMessage + 'static>()
+-> impl FnMut(Option<Res<StdbTableChanged<T>>>) -> bool + Clone {
+    |changed| changed.is_some_and(|changed| changed.is_changed())
+}
+
+/// Marks [`StdbTableChanged<T>`] as changed when a message of type `E` was emitted for the table.
+fn track_table_changes<E: Message, T: TableMessage + 'static>(
+    mut messages: MessageReader<E>,
+    mut changed: ResMut<StdbTableChanged<T>>,
+) {
+    if !messages.is_empty() {
+        messages.clear();
+        changed.set_changed();
+    }
+}
+
+/// Adds the [`StdbTableChanged<T>`] resource, tracking the given message types.
+pub(crate) fn register_table_changes<T: TableMessage + 'static>(
+    app: &mut App,
+    insert: bool,
+    update: bool,
+    delete: bool,
+) {
+    app.init_resource::<StdbTableChanged<T>>();
+    if insert {
+        app.add_systems(
+            PreUpdate,
+            track_table_changes::<InsertMessage<T>, T>.after(MessageChannelSystems),
+        );
+    }
+    if update {
+        app.add_systems(
+            PreUpdate,
+            track_table_changes::<UpdateMessage<T>, T>.after(MessageChannelSystems),
+        );
+    }
+    if delete {
+        app.add_systems(
+            PreUpdate,
+            track_table_changes::<DeleteMessage<T>, T>.after(MessageChannelSystems),
+        );
+    }
+}
+
 impl<
     C: spacetime_codegen::DbConnection<Module = M> + spacetimedb_sdk::DbContext,
     M: spacetime_codegen::SpacetimeModule<DbConnection = C>,
@@ -113,31 +189,54 @@ impl<
 
     ///Registers a table for the bevy application with the specified messages in the `messages` parameter.
     pub fn add_partial_table<T: RegisterableTable<C, M> + Send + Sync + 'static>(
-        mut self,
+        self,
         messages: TableMessages,
     ) -> Self {
+        if messages.insert {
+            self.register_channel::<InsertMessage<T::Message>>();
+        }
+        if messages.delete {
+            self.register_channel::<DeleteMessage<T::Message>>();
+        }
+        if messages.update {
+            self.register_channel::<UpdateMessage<T::Message>>();
+        }
+        if messages.update && messages.insert {
+            self.register_channel::<InsertUpdateMessage<T::Message>>();
+        }
+
         // A closure that sets up messages for the table
-        let register = move |plugin: &Self, app: &mut App, db: &'static C::DbView| {
+        let transactions = Arc::clone(&self.transactions);
+        let register = move |senders: &MessageSenders, db: &C::DbView| {
             if messages.insert {
-                plugin.on_insert::<T>(app, db);
+                Self::on_insert::<T>(senders, &transactions, db);
             }
             if messages.delete {
-                plugin.on_delete::<T>(app, db);
+                Self::on_delete::<T>(senders, &transactions, db);
             }
             if messages.update {
-                plugin.on_update::<T>(app, db);
+                Self::on_update::<T>(senders, &transactions, db);
             }
             if messages.update && messages.insert {
-                plugin.on_insert_update::<T>(app, db);
+                Self::on_insert_update::<T>(senders, &transactions, db);
             }
         };
 
-        // Store this table, and later when the plugin is built, call them on .
+        // Store this table, and call it on every new connection.
         self.table_registers
             .lock()
             .unwrap()
             .push(Box::new(register));
 
+        self.system_registers.lock().unwrap().push(Box::new(move |app: &mut App| {
+            register_table_changes::<T::Message>(
+                app,
+                messages.insert,
+                messages.update,
+                messages.delete,
+            );
+        }));
+
         self
     }
 
@@ -150,224 +249,268 @@ impl<
 
     ///Registers a table without primary key for the bevy application with the specified messages in the `messages` parameter.
     pub fn add_partial_table_without_pk<T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static>(
-        mut self,
+        self,
         messages: TableMessagesWithoutPrimaryKey,
     ) -> Self {
+        if messages.insert {
+            self.register_channel::<InsertMessage<T::Message>>();
+        }
+        if messages.delete {
+            self.register_channel::<DeleteMessage<T::Message>>();
+        }
+
         // A closure that sets up messages for the table
-        let register = move |plugin: &Self, app: &mut App, db: &'static C::DbView| {
+        let transactions = Arc::clone(&self.transactions);
+        let register = move |senders: &MessageSenders, db: &C::DbView| {
             if messages.insert {
-                plugin.on_insert_without_pk::<T>(app, db);
+                Self::on_insert_without_pk::<T>(senders, &transactions, db);
             }
             if messages.delete {
-                plugin.on_delete_without_pk::<T>(app, db);
+                Self::on_delete_without_pk::<T>(senders, &transactions, db);
             }
         };
-        // Store this table, and later when the plugin is built, call them on .
+        // Store this table, and call it on every new connection.
         self.table_registers
             .lock()
             .unwrap()
             .push(Box::new(register));
 
+        self.system_registers.lock().unwrap().push(Box::new(move |app: &mut App| {
+            register_table_changes::<T::Message>(app, messages.insert, false, messages.delete);
+        }));
+
+        self
+    }
+
+    /// Registers an append-only event table, e.g. chat lines or hit markers, emitting only its [`InsertMessage`].
+    ///
+    /// The table is registered like a table without primary key (derive `RegisterTableWithoutPk`),
+    /// so a primary key isn't needed. Only the rows inserted by a server update are emitted: the rows already
+    /// in the table when subscribing, carrying an `Event::SubscribeApplied` event, were emitted before
+    /// or happened while disconnected, and are skipped on every (re)connection.
+    /// The rows stay in the client cache until the module deletes them or the subscription ends,
+    /// so the module should clean up old rows to keep the cache small.
+    pub fn add_event_table<T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static>(
+        self,
+    ) -> Self {
+        self.register_channel::<InsertMessage<T::Message>>();
+
+        // A closure that sets up messages for the table
+        let transactions = Arc::clone(&self.transactions);
+        let register = move |senders: &MessageSenders, db: &C::DbView| {
+            Self::on_event_insert::<T>(senders, &transactions, db);
+        };
+        // Store this table, and call it on every new connection.
+        self.table_registers
+            .lock()
+            .unwrap()
+            .push(Box::new(register));
+
+        self.system_registers.lock().unwrap().push(Box::new(|app: &mut App| {
+            register_table_changes::<T::Message>(app, true, false, false);
+        }));
+
         self
     }
 
     /// Register a Bevy message of type InsertMessage<TRow> for the `on_insert` message on the provided table.
     fn on_insert<T: RegisterableTable<C, M> + Send + Sync + 'static>(
-        &self,
-        app: &mut App,
-        db: &'static C::DbView,
-    ) -> &Self {
-        let type_id = TypeId::of::<InsertMessage<T::Message>>();
-
-        let mut map = self.message_senders.lock().unwrap();
-
-        let sender = map
-            .entry(type_id)
-            .or_insert_with(|| {
-                let (send, recv) = channel::<InsertMessage<T::Message>>();
-                app.add_message_channel(recv);
-                Box::new(send)
-            })
-            .downcast_ref::<Sender<InsertMessage<T::Message>>>()
-            .expect("Sender type mismatch")
-            .clone();
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let sender = message_sender::<InsertMessage<T::Message>>(senders);
 
+        let transactions = Arc::clone(transactions);
         T::table_accessor(db).on_insert(move |_ctx, row| {
-            let message = InsertMessage {
-                event: T::context_event_accessor(_ctx),
-                row: row.clone(),
-            };
-            let _ = sender.send(message);
+            let (sender, row) = (sender.clone(), row.clone());
+            transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = sender.send(InsertMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        row,
+                    });
+                })
+            });
         });
-
-        self
     }
 
     /// Register a Bevy message of type DeleteMessage<TRow> for the `on_delete` message on the provided table.
     fn on_delete<T: RegisterableTable<C, M> + Send + Sync + 'static>(
-        &self,
-        app: &mut App,
-        db: &'static C::DbView,
-    ) -> &Self {
-        let type_id = TypeId::of::<DeleteMessage<T::Message>>();
-
-        let mut map = self.message_senders.lock().unwrap();
-        let sender = map
-            .entry(type_id)
-            .or_insert_with(|| {
-                let (send, recv) = channel::<DeleteMessage<T::Message>>();
-                app.add_message_channel(recv);
-                Box::new(send)
-            })
-            .downcast_ref::<Sender<DeleteMessage<T::Message>>>()
-            .expect("Sender type mismatch")
-            .clone();
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let sender = message_sender::<DeleteMessage<T::Message>>(senders);
 
+        let transactions = Arc::clone(transactions);
         T::table_accessor(db).on_delete(move |_ctx, row| {
-            let message = DeleteMessage {
-                event: T::context_event_accessor(_ctx),
-                row: row.clone(),
-            };
-            let _ = sender.send(message);
+            let (sender, row) = (sender.clone(), row.clone());
+            transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = sender.send(DeleteMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        row,
+                    });
+                })
+            });
         });
-
-        self
     }
 
     /// Register a Bevy message of type UpdateMessage<TRow> for the `on_update` message on the provided table.
     fn on_update<T: RegisterableTable<C, M> + Send + Sync + 'static>(
-        &self,
-        app: &mut App,
-        db: &'static C::DbView,
-    ) -> &Self {
-        let type_id = TypeId::of::<UpdateMessage<T::Message>>();
-
-        let mut map = self.message_senders.lock().unwrap();
-        let sender = map
-            .entry(type_id)
-            .or_insert_with(|| {
-                let (send, recv) = channel::<UpdateMessage<T::Message>>();
-                app.add_message_channel(recv);
-                Box::new(send)
-            })
-            .downcast_ref::<Sender<UpdateMessage<T::Message>>>()
-            .expect("Sender type mismatch")
-            .clone();
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let sender = message_sender::<UpdateMessage<T::Message>>(senders);
 
+        let transactions = Arc::clone(transactions);
         T::table_accessor(db).on_update(move |_ctx, old, new| {
-            let message = UpdateMessage {
-                event: T::context_event_accessor(_ctx),
-                old: old.clone(),
-                new: new.clone(),
-            };
-            let _ = sender.send(message);
+            let (sender, old, new) = (sender.clone(), old.clone(), new.clone());
+            transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = sender.send(UpdateMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        old,
+                        new,
+                    });
+                })
+            });
         });
-
-        self
     }
 
     /// Register a Bevy message of type InsertUpdateMessage<TRow> for the `on_insert` and `on_update` messages on the provided table.
     fn on_insert_update<T: RegisterableTable<C, M> + Send + Sync + 'static>(
-        &self,
-        app: &mut App,
-        db: &'static C::DbView,
-    ) -> &Self {
-        let type_id = TypeId::of::<InsertUpdateMessage<T::Message>>();
-
-        let mut map = self.message_senders.lock().unwrap();
-        let send = map
-            .entry(type_id)
-            .or_insert_with(|| {
-                let (send, recv) = channel::<InsertUpdateMessage<T::Message>>();
-                app.add_message_channel(recv);
-                Box::new(send)
-            })
-            .downcast_ref::<Sender<InsertUpdateMessage<T::Message>>>()
-            .expect("Sender type mismatch")
-            .clone();
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let send = message_sender::<InsertUpdateMessage<T::Message>>(senders);
 
         let send_update = send.clone();
+        let update_transactions = Arc::clone(transactions);
         T::table_accessor(db).on_update(move |_ctx, old, new| {
-            let message = InsertUpdateMessage {
-                event: T::context_event_accessor(_ctx),
-                old: Some(old.clone()),
-                new: new.clone(),
-            };
-            let _ = send_update.send(message);
+            let (send_update, old, new) = (send_update.clone(), old.clone(), new.clone());
+            update_transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = send_update.send(InsertUpdateMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        old: Some(old),
+                        new,
+                    });
+                })
+            });
         });
 
+        let transactions = Arc::clone(transactions);
         T::table_accessor(db).on_insert(move |_ctx, row| {
-            let message = InsertUpdateMessage {
-                event: T::context_event_accessor(_ctx),
-                old: None,
-                new: row.clone(),
-            };
-            let _ = send.send(message);
+            let (send, row) = (send.clone(), row.clone());
+            transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = send.send(InsertUpdateMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        old: None,
+                        new: row,
+                    });
+                })
+            });
         });
-
-        self
     }
 
     /// Register a Bevy message of type InsertMessage<TRow> for the `on_insert` message on a table without primary key.
     fn on_insert_without_pk<T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static>(
-        &self,
-        app: &mut App,
-        db: &'static C::DbView,
-    ) -> &Self {
-        let type_id = TypeId::of::<InsertMessage<T::Message>>();
-
-        let mut map = self.message_senders.lock().unwrap();
-
-        let sender = map
-            .entry(type_id)
-            .or_insert_with(|| {
-                let (send, recv) = channel::<InsertMessage<T::Message>>();
-                app.add_message_channel(recv);
-                Box::new(send)
-            })
-            .downcast_ref::<Sender<InsertMessage<T::Message>>>()
-            .expect("Sender type mismatch")
-            .clone();
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let sender = message_sender::<InsertMessage<T::Message>>(senders);
 
+        let transactions = Arc::clone(transactions);
         T::table_accessor(db).on_insert(move |_ctx, row| {
-            let message = InsertMessage {
-                event: T::context_event_accessor(_ctx),
-                row: row.clone(),
-            };
-            let _ = sender.send(message);
+            let (sender, row) = (sender.clone(), row.clone());
+            transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = sender.send(InsertMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        row,
+                    });
+                })
+            });
         });
+    }
 
-        self
+    /// Register a Bevy message of type InsertMessage<TRow> for the rows inserted by server updates in an event table.
+    fn on_event_insert<T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static>(
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let sender = message_sender::<InsertMessage<T::Message>>(senders);
+
+        let transactions = Arc::clone(transactions);
+        T::table_accessor(db).on_insert(move |_ctx, row| {
+            let event = T::context_event_accessor(_ctx);
+            // The rows already in the table when subscribing are past events
+            if matches!(event, Event::SubscribeApplied) {
+                return;
+            }
+
+            let (sender, row) = (sender.clone(), row.clone());
+            transactions.send(event, |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = sender.send(InsertMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        row,
+                    });
+                })
+            });
+        });
     }
 
     /// Register a Bevy message of type DeleteMessage<TRow> for the `on_delete` message on a table without primary key.
     fn on_delete_without_pk<T: RegisterableTableWithoutPk<C, M> + Send + Sync + 'static>(
-        &self,
-        app: &mut App,
-        db: &'static C::DbView,
-    ) -> &Self {
-        let type_id = TypeId::of::<DeleteMessage<T::Message>>();
-
-        let mut map = self.message_senders.lock().unwrap();
-        let sender = map
-            .entry(type_id)
-            .or_insert_with(|| {
-                let (send, recv) = channel::<DeleteMessage<T::Message>>();
-                app.add_message_channel(recv);
-                Box::new(send)
-            })
-            .downcast_ref::<Sender<DeleteMessage<T::Message>>>()
-            .expect("Sender type mismatch")
-            .clone();
+        senders: &MessageSenders,
+        transactions: &Arc<Transactions>,
+        db: &C::DbView,
+    ) {
+        let sender = message_sender::<DeleteMessage<T::Message>>(senders);
 
+        let transactions = Arc::clone(transactions);
         T::table_accessor(db).on_delete(move |_ctx, row| {
-            let message = DeleteMessage {
-                event: T::context_event_accessor(_ctx),
-                row: row.clone(),
-            };
-            let _ = sender.send(message);
+            let (sender, row) = (sender.clone(), row.clone());
+            transactions.send(T::context_event_accessor(_ctx), |event, transaction| {
+                let sequence = StdbSequence::next();
+                Box::new(move || {
+                    let _ = sender.send(DeleteMessage {
+                        sequence,
+                        event,
+                        transaction,
+                        row,
+                    });
+                })
+            });
         });
-
-        self
     }
 }
@@ -0,0 +1,268 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use bevy::prelude::{Local, MessageWriter, Res, Resource};
+use spacetimedb_sdk::{ConnectionId, Event, Identity, Timestamp};
+
+use crate::{StdbSequence, StdbTransactionMessage};
+
+/// Identifies the server update (usually a reducer transaction) a table message belongs to.
+///
+/// Every row change of the same update carries the same id, and ids increase with each update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StdbTransactionId(pub(crate) u64);
+
+/// What the row callbacks of a server update have in common, used to find where an update ends.
+#[derive(PartialEq)]
+enum TransactionKey {
+    Reducer {
+        timestamp: Timestamp,
+        caller_identity: Identity,
+        caller_connection_id: Option<ConnectionId>,
+    },
+    SubscribeApplied,
+    UnsubscribeApplied,
+    // Consecutive updates that can't be told apart are grouped together
+    Unknown,
+}
+
+impl TransactionKey {
+    fn of<R>(event: &Event<R>) -> Self {
+        match event {
+            Event::Reducer(event) => Self::Reducer {
+                timestamp: event.timestamp,
+                caller_identity: event.caller_identity,
+                caller_connection_id: event.caller_connection_id,
+            },
+            Event::SubscribeApplied => Self::SubscribeApplied,
+            Event::UnsubscribeApplied => Self::UnsubscribeApplied,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+type SendFn = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct TransactionsState {
+    next_id: u64,
+    current: Option<(StdbTransactionId, TransactionKey)>,
+    /// Messages of the current transaction, held back until it is complete.
+    pending: Vec<SendFn>,
+    /// Messages of the complete transactions, sent on the next flush.
+    ready: Vec<SendFn>,
+    completed: Vec<(StdbTransactionId, StdbSequence)>,
+    /// Number of row messages received, to notice when the current transaction stopped receiving rows.
+    received: u64,
+}
+
+impl TransactionsState {
+    fn complete_current(&mut self) {
+        if let Some((id, _)) = self.current.take() {
+            self.ready.append(&mut self.pending);
+            // Ordered after the rows of the transaction and before those of the next one
+            self.completed.push((id, StdbSequence::next()));
+        }
+    }
+}
+
+/// Groups the table messages of a plugin by server update, shared by all its table callbacks.
+///
+/// The SDK invokes every row callback of an update back to back on the thread running the connection,
+/// so an update ends when a row with a different event arrives, or once the frame tick processed it.
+/// When the connection runs on its own thread, an update is also considered over when no row arrived for a whole
+/// frame: this is only a guess, a slow update can be split in two.
+#[derive(Default)]
+pub(crate) struct Transactions {
+    batching: AtomicBool,
+    state: Mutex<TransactionsState>,
+}
+
+impl Transactions {
+    pub(crate) fn set_batching(&self, batching: bool) {
+        self.batching.store(batching, Ordering::Relaxed);
+    }
+
+    /// Sends the message built by `message` for a row changed by the given event,
+    /// or holds it back until its transaction is complete when batching.
+    pub(crate) fn send<R>(
+        &self,
+        event: Event<R>,
+        message: impl FnOnce(Event<R>, StdbTransactionId) -> SendFn,
+    ) {
+        self.send_with(event, message, self.batching.load(Ordering::Relaxed));
+    }
+
+    /// Like [`Transactions::send`], but always holds the message back until its transaction is complete,
+    /// for messages that depend on every row of the transaction.
+    pub(crate) fn send_on_complete<R>(
+        &self,
+        event: Event<R>,
+        message: impl FnOnce(Event<R>, StdbTransactionId) -> SendFn,
+    ) {
+        self.send_with(event, message, true);
+    }
+
+    fn send_with<R>(
+        &self,
+        event: Event<R>,
+        message: impl FnOnce(Event<R>, StdbTransactionId) -> SendFn,
+        hold: bool,
+    ) {
+        let key = TransactionKey::of(&event);
+        let mut state = self.state.lock().unwrap();
+        state.received += 1;
+
+        let id = match &state.current {
+            Some((id, current)) if *current == key => *id,
+            _ => {
+                state.complete_current();
+                let id = StdbTransactionId(state.next_id);
+                state.next_id += 1;
+                state.current = Some((id, key));
+                id
+            }
+        };
+
+        let send = message(event, id);
+        if hold {
+            state.pending.push(send);
+        } else {
+            send();
+        }
+    }
+
+    /// Completes the current transaction, called once the connection has processed all pending updates.
+    pub(crate) fn complete_current(&self) {
+        self.state.lock().unwrap().complete_current();
+    }
+}
+
+/// The [`Transactions`] of the plugin for the connection `C`, as a Bevy resource.
+#[derive(Resource)]
+pub(crate) struct StdbTransactions<C: Send + Sync + 'static> {
+    pub(crate) transactions: Arc<Transactions>,
+    pub(crate) _phantom: PhantomData<C>,
+}
+
+/// Sends the messages of the complete transactions to their channel, before the channel bridge forwards them,
+/// and emits a [`StdbTransactionMessage`] for each of them.
+pub(crate) fn flush_transactions<C: Send + Sync + 'static>(
+    transactions: Res<StdbTransactions<C>>,
+    mut last_received: Local<u64>,
+    mut writer: MessageWriter<StdbTransactionMessage<C>>,
+) {
+    let mut state = transactions.transactions.state.lock().unwrap();
+
+    // No row arrived since the last frame, the current transaction is most likely over.
+    // Without it, the last update before a quiet period would be held back until the next one.
+    if state.received == *last_received {
+        state.complete_current();
+    }
+    *last_received = state.received;
+
+    for send in state.ready.drain(..) {
+        send();
+    }
+    for (transaction, sequence) in state.completed.drain(..) {
+        writer.write(StdbTransactionMessage {
+            sequence,
+            transaction,
+            _phantom: PhantomData,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a message recording its transaction in `sent`, and returns the transaction.
+    fn send(
+        transactions: &Transactions,
+        event: Event<()>,
+        hold: bool,
+        sent: &Arc<Mutex<Vec<StdbTransactionId>>>,
+    ) -> StdbTransactionId {
+        let mut id = None;
+        transactions.send_with(
+            event,
+            |_, transaction| {
+                id = Some(transaction);
+                let sent = Arc::clone(sent);
+                Box::new(move || sent.lock().unwrap().push(transaction))
+            },
+            hold,
+        );
+        id.unwrap()
+    }
+
+    #[test]
+    fn rows_of_the_same_event_share_a_transaction() {
+        let (transactions, sent) = (Transactions::default(), Arc::default());
+
+        let first = send(&transactions, Event::SubscribeApplied, false, &sent);
+        let second = send(&transactions, Event::SubscribeApplied, false, &sent);
+        let third = send(&transactions, Event::UnsubscribeApplied, false, &sent);
+        let fourth = send(&transactions, Event::SubscribeApplied, false, &sent);
+
+        assert_eq!(first, second);
+        assert!(second < third);
+        assert!(third < fourth);
+        assert_eq!(*sent.lock().unwrap(), vec![first, second, third, fourth]);
+    }
+
+    #[test]
+    fn unknown_events_are_grouped_together() {
+        let (transactions, sent) = (Transactions::default(), Arc::default());
+
+        let first = send(&transactions, Event::UnknownTransaction, false, &sent);
+        let second = send(&transactions, Event::UnknownTransaction, false, &sent);
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn held_messages_wait_for_their_transaction_to_complete() {
+        let (transactions, sent) = (Transactions::default(), Arc::default());
+
+        let first = send(&transactions, Event::SubscribeApplied, true, &sent);
+        send(&transactions, Event::SubscribeApplied, true, &sent);
+        {
+            let state = transactions.state.lock().unwrap();
+            assert_eq!(state.pending.len(), 2);
+            assert!(state.ready.is_empty());
+            assert!(state.completed.is_empty());
+        }
+
+        // A row of another event completes the first transaction
+        let second = send(&transactions, Event::UnsubscribeApplied, true, &sent);
+        {
+            let state = transactions.state.lock().unwrap();
+            assert_eq!(state.pending.len(), 1);
+            assert_eq!(state.ready.len(), 2);
+            assert_eq!(
+                state.completed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+                vec![first]
+            );
+        }
+
+        transactions.complete_current();
+        let mut state = transactions.state.lock().unwrap();
+        assert!(state.pending.is_empty());
+        assert!(state.current.is_none());
+        for send in state.ready.drain(..) {
+            send();
+        }
+        assert_eq!(*sent.lock().unwrap(), vec![first, first, second]);
+        assert_eq!(
+            state.completed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use heck::ToSnakeCase;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, parse_str, Data, DeriveInput, Fields, Ident, Path};
+use syn::{parse::Parse, parenthesized, parse_macro_input, parse_str, Data, DeriveInput, Fields, Ident, Path, Token};
 
 /// This macro automatically generates the boilerplate code needed to register a reducer
 /// with the `StdbPlugin`.
@@ -69,12 +69,11 @@ pub fn register_reducer_message_derive(input: TokenStream) -> TokenStream {
         impl bevy_spacetimedb::RegisterableReducerMessage<DbConnection, RemoteModule> for #struct_name {
             fn set_stdb_callback(reducers: &RemoteReducers, sender: std::sync::mpsc::Sender<bevy_spacetimedb::ReducerResultMessage<Self>>) {
                 reducers.#function_name(move |ctx, #(#param_idents),*| {
-                    sender
-                        .send(bevy_spacetimedb::ReducerResultMessage::new(#struct_name {
-                            event: ctx.event.clone(),
-                            #(#param_idents: #param_idents.clone()),*
-                        }))
-                        .unwrap();
+                    // The receiver is gone once the app exits, the result can be dropped then.
+                    let _ = sender.send(bevy_spacetimedb::ReducerResultMessage::new(#struct_name {
+                        event: ctx.event.clone(),
+                        #(#param_idents: #param_idents.clone()),*
+                    }));
                 });
             }
         }
@@ -83,6 +82,161 @@ pub fn register_reducer_message_derive(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// This macro implements `CallableReducer` for a struct holding the arguments of a reducer,
+/// so it can be called by writing a `CallReducer` message and its results reported.
+///
+/// ## Requirements
+///
+/// - The fields must match the reducer's parameter types and order
+/// - Struct fields must be named (no tuple structs)
+/// - The reducer is named after the struct in snake case, or after the `#[reducer(name)]` attribute
+///
+/// ## Example
+///
+///```no-run
+/// #[derive(CallableReducer)]
+/// #[reducer(move_player)]
+/// pub struct MovePlayerCall {
+///     pub x: f32,
+///     pub y: f32,
+/// }
+/// ```
+#[proc_macro_derive(CallableReducer, attributes(reducer))]
+pub fn callable_reducer_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let struct_name = &input.ident;
+
+    // Reducer name from the attribute, or derived from the struct name
+    let function_name = input
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("reducer"))
+        .map(|attr| {
+            attr.parse_args::<Ident>()
+                .expect("Expected #[reducer(reducer_name)]")
+        })
+        .unwrap_or_else(|| {
+            Ident::new(&struct_name.to_string().to_snake_case(), struct_name.span())
+        });
+
+    // Extract named fields
+    let fields = match input.data {
+        Data::Struct(data_struct) => match data_struct.fields {
+            Fields::Named(fields_named) => fields_named.named,
+            _ => panic!("Struct must have named fields"),
+        },
+        _ => panic!("Only structs are supported"),
+    };
+    let param_idents = fields
+        .iter()
+        .map(|field| field.ident.clone().expect("Field must have identifier"));
+    // The callback arguments are ignored, only the event is reported
+    let ignored_params = fields.iter().map(|_| quote!(_));
+    let callback_name = Ident::new(&format!("on_{}", function_name), function_name.span());
+
+    // Generate the implementation
+    let expanded = quote! {
+        impl bevy_spacetimedb::CallableReducer<DbConnection, RemoteModule> for #struct_name {
+            fn call(&self, reducers: &RemoteReducers) -> spacetimedb_sdk::Result<()> {
+                reducers.#function_name(#(self.#param_idents.clone()),*)
+            }
+
+            fn on_call_result(
+                reducers: &RemoteReducers,
+                mut callback: Box<dyn FnMut(&spacetimedb_sdk::ReducerEvent<Reducer>) + Send>,
+            ) {
+                reducers.#callback_name(move |ctx, #(#ignored_params),*| {
+                    if ctx.event.caller_connection_id == Some(spacetimedb_sdk::DbContext::connection_id(ctx)) {
+                        callback(&ctx.event);
+                    }
+                });
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// This macro automatically generates the boilerplate code needed to register a procedure
+/// with the `StdbPlugin`.
+///
+/// ## Requirements
+///
+/// - The struct must have exactly one field named `result`, with the procedure's return type
+/// - All other fields must match the procedure's parameter types and order
+/// - Struct fields must be named (no tuple structs)
+///
+/// ## Example
+///
+///```no-run
+/// #[derive(RegisterProcedureMessage)]
+/// pub struct RollDice {
+///     pub result: u32,
+///     pub sides: u32,
+/// }
+/// ```
+#[proc_macro_derive(RegisterProcedureMessage)]
+pub fn register_procedure_message_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    TokenStream::from(register_procedure_message(input))
+}
+
+/// Expands the `RegisterProcedureMessage` derive, outside of the proc-macro API so it can be tested.
+fn register_procedure_message(input: DeriveInput) -> proc_macro2::TokenStream {
+    let struct_name = &input.ident;
+    let struct_name_str = struct_name.to_string();
+
+    // Derive callback name directly from struct name (no suffix stripping)
+    let function_name = Ident::new(
+        &format!("on_{}", struct_name_str.to_snake_case()),
+        struct_name.span(),
+    );
+
+    // Extract named fields
+    let fields = match input.data {
+        Data::Struct(data_struct) => match data_struct.fields {
+            Fields::Named(fields_named) => fields_named.named,
+            _ => panic!("Struct must have named fields"),
+        },
+        _ => panic!("Only structs are supported"),
+    };
+
+    // Separate 'result' field from procedure parameters
+    let mut result_field = None;
+    let mut param_idents = Vec::new();
+
+    for field in fields {
+        let field_ident = field.ident.as_ref().expect("Field must have identifier");
+        if field_ident == "result" {
+            if result_field.is_some() {
+                panic!("Duplicate 'result' field");
+            }
+            result_field = Some(field);
+        } else {
+            param_idents.push(field_ident.clone());
+        }
+    }
+
+    if result_field.is_none() {
+        panic!("Struct must have a 'result' field");
+    }
+
+    // Generate the implementation
+    quote! {
+        impl bevy_spacetimedb::RegisterableProcedureMessage<DbConnection, RemoteModule> for #struct_name {
+            fn set_stdb_callback(procedures: &RemoteProcedures, sender: std::sync::mpsc::Sender<bevy_spacetimedb::ProcedureResultMessage<Self>>) {
+                procedures.#function_name(move |_ctx, #(#param_idents,)* result| {
+                    // The receiver is gone once the app exits, the result can be dropped then.
+                    let _ = sender.send(bevy_spacetimedb::ProcedureResultMessage::new(#struct_name {
+                        result: result.clone(),
+                        #(#param_idents: #param_idents.clone()),*
+                    }));
+                });
+            }
+        }
+    }
+}
+
 #[proc_macro_derive(RegisterTable)]
 pub fn register_table_derive(input: TokenStream) -> TokenStream {
     register_table(
@@ -115,14 +269,15 @@ fn register_table(trait_name: Path, input: TokenStream) -> TokenStream {
         impl #trait_name<DbConnection, RemoteModule> for #struct_name {
             type Row = <#table_handle_name<'static> as spacetimedb_sdk::Table>::Row;
             type Reducer = Reducer;
-            type Table = #table_handle_name<'static>;
+            type EventContext = <#table_handle_name<'static> as spacetimedb_sdk::Table>::EventContext;
+            type Table<'a> = #table_handle_name<'a>;
             type Message = Self;
 
-            fn table_accessor(db_context: &'static RemoteTables) -> Self::Table {
+            fn table_accessor(db_context: &RemoteTables) -> Self::Table<'_> {
                 db_context.#table_name_snake_case()
             }
 
-            fn context_event_accessor(ctx: &<Self::Table as spacetimedb_sdk::Table>::EventContext) -> spacetimedb_sdk::Event<Self::Reducer> {
+            fn context_event_accessor(ctx: &Self::EventContext) -> spacetimedb_sdk::Event<Self::Reducer> {
                 ctx.event.clone()
             }
         }
@@ -134,3 +289,140 @@ fn register_table(trait_name: Path, input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+/// This macro implements `StdbRowToComponents` for a table, from one `#[component(...)]`
+/// attribute per projected component.
+///
+/// ## Attributes
+///
+/// - `#[component(Type, with = function)]`: `function(&Row) -> Type` builds the component from the row.
+///   It is re-inserted whenever the row is updated.
+/// - `#[component(Type, fields(a, b), with = function)]`: the component is only re-inserted
+///   when one of the listed row fields changed.
+///
+/// ## Example
+///
+///```no-run
+/// #[derive(RegisterTable, StdbRowToComponents)]
+/// #[component(Transform, fields(x, y, z), with = player_transform)]
+/// #[component(Name, fields(name), with = player_name)]
+/// pub struct PlayersTable;
+///
+/// fn player_transform(player: &Player) -> Transform {
+///     Transform::from_xyz(player.x, player.y, player.z)
+/// }
+/// ```
+#[proc_macro_derive(StdbRowToComponents, attributes(component))]
+pub fn stdb_row_to_components_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let struct_name = &input.ident;
+
+    let mut inserts = Vec::new();
+    let mut updates = Vec::new();
+
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("component")) {
+        let mut component = None;
+        let mut fields: Option<Vec<Ident>> = None;
+        let mut with = None;
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("with") {
+                with = Some(meta.value()?.parse::<Path>()?);
+            } else if meta.path.is_ident("fields") {
+                let content;
+                parenthesized!(content in meta.input);
+                let idents = content.parse_terminated(Ident::parse, Token![,])?;
+                fields = Some(idents.into_iter().collect());
+            } else if component.is_none() {
+                component = Some(meta.path);
+            } else {
+                return Err(meta.error("unsupported component attribute"));
+            }
+            Ok(())
+        })
+        .expect("Invalid #[component(...)] attribute");
+
+        let component = component.expect("#[component(...)] must start with the component type");
+        let with = with.expect("#[component(...)] must have a `with = function` argument");
+
+        inserts.push(quote! {
+            let component: #component = #with(row);
+            entity.try_insert(component);
+        });
+
+        // Without fields, any change of the row re-inserts the component
+        let changed = match fields {
+            Some(fields) => quote! { #(old.#fields != new.#fields)||* },
+            None => quote! { old != new },
+        };
+        updates.push(quote! {
+            if #changed {
+                let component: #component = #with(new);
+                entity.try_insert(component);
+            }
+        });
+    }
+
+    let expanded = quote! {
+        impl bevy_spacetimedb::StdbRowToComponents for #struct_name {
+            fn insert_components(row: &<Self as bevy_spacetimedb::TableMessage>::Row, entity: &mut bevy::ecs::system::EntityCommands) {
+                #(#inserts)*
+            }
+
+            fn update_components(old: &<Self as bevy_spacetimedb::TableMessage>::Row, new: &<Self as bevy_spacetimedb::TableMessage>::Row, entity: &mut bevy::ecs::system::EntityCommands) {
+                #(#updates)*
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::{ItemImpl, parse_quote};
+
+    use super::*;
+
+    #[test]
+    fn procedure_message_forwards_the_arguments_and_result() {
+        let input: DeriveInput = parse_quote! {
+            pub struct RollDice {
+                pub result: u32,
+                pub sides: u32,
+                pub count: u8,
+            }
+        };
+
+        let expanded = register_procedure_message(input);
+
+        // The expansion must be a valid impl block
+        syn::parse2::<ItemImpl>(expanded.clone()).expect("The expansion is not an impl block");
+        let expected = quote! {
+            impl bevy_spacetimedb::RegisterableProcedureMessage<DbConnection, RemoteModule> for RollDice {
+                fn set_stdb_callback(procedures: &RemoteProcedures, sender: std::sync::mpsc::Sender<bevy_spacetimedb::ProcedureResultMessage<Self>>) {
+                    procedures.on_roll_dice(move |_ctx, sides, count, result| {
+                        let _ = sender.send(bevy_spacetimedb::ProcedureResultMessage::new(RollDice {
+                            result: result.clone(),
+                            sides: sides.clone(),
+                            count: count.clone()
+                        }));
+                    });
+                }
+            }
+        };
+        assert_eq!(expanded.to_string(), expected.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Struct must have a 'result' field")]
+    fn procedure_message_requires_a_result_field() {
+        let input: DeriveInput = parse_quote! {
+            pub struct RollDice {
+                pub sides: u32,
+            }
+        };
+
+        register_procedure_message(input);
+    }
+}